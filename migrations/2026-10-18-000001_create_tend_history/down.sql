@@ -0,0 +1,14 @@
+ALTER TABLE auction_info
+    ADD COLUMN tenders_id BIGINT[] NOT NULL DEFAULT '{}',
+    ADD COLUMN tends_price INTEGER[] NOT NULL DEFAULT '{}';
+
+UPDATE auction_info info
+SET tenders_id = history.tenders_id, tends_price = history.tends_price
+FROM (
+    SELECT auction_id, array_agg(tender_id ORDER BY id) AS tenders_id, array_agg(price ORDER BY id) AS tends_price
+    FROM tend_history
+    GROUP BY auction_id
+) history
+WHERE info.id = history.auction_id;
+
+DROP TABLE tend_history;
@@ -0,0 +1,22 @@
+CREATE TABLE tend_history (
+    id SERIAL PRIMARY KEY,
+    auction_id INTEGER NOT NULL REFERENCES auction_info (id) ON DELETE CASCADE,
+    tender_id BIGINT NOT NULL,
+    price INTEGER NOT NULL,
+    tended_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    message_id BIGINT,
+    tender_name TEXT NOT NULL DEFAULT ''
+);
+CREATE INDEX tend_history_auction_id_idx ON tend_history (auction_id, id);
+
+-- 既存の入札配列を1入札1行に移す
+-- 配列には入札時刻・メッセージが残っていないため、時刻は移行時刻、メッセージIDはNULLになる
+INSERT INTO tend_history (auction_id, tender_id, price, tended_at)
+SELECT info.id, tend.tender_id, tend.price, CURRENT_TIMESTAMP
+FROM auction_info info,
+     unnest(info.tenders_id, info.tends_price) WITH ORDINALITY AS tend(tender_id, price, ord)
+ORDER BY info.id, tend.ord;
+
+ALTER TABLE auction_info
+    DROP COLUMN tenders_id,
+    DROP COLUMN tends_price;
@@ -0,0 +1,4 @@
+ALTER TABLE auction_info
+    DROP CONSTRAINT auction_info_min_increment_exclusive,
+    DROP COLUMN min_increment,
+    DROP COLUMN min_increment_percent;
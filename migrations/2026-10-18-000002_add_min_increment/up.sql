@@ -0,0 +1,5 @@
+-- 最低入札単位。金額指定(min_increment)と現在価格に対する割合指定(min_increment_percent)のどちらか一方のみ設定できる
+ALTER TABLE auction_info
+    ADD COLUMN min_increment INTEGER CHECK (min_increment > 0),
+    ADD COLUMN min_increment_percent INTEGER CHECK (min_increment_percent BETWEEN 1 AND 100),
+    ADD CONSTRAINT auction_info_min_increment_exclusive CHECK (min_increment IS NULL OR min_increment_percent IS NULL);
@@ -0,0 +1,9 @@
+ALTER TABLE auction_info
+    DROP CONSTRAINT auction_info_extension_pair,
+    DROP COLUMN extension_window,
+    DROP COLUMN extension_minutes;
+
+ALTER TABLE channel_auction
+    DROP CONSTRAINT channel_auction_extension_pair,
+    DROP COLUMN extension_window,
+    DROP COLUMN extension_minutes;
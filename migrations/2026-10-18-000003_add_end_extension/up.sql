@@ -0,0 +1,12 @@
+-- 終了間際の入札による終了日時の延長設定
+-- 終了extension_window分前以降に入札があった場合、終了日時を入札からextension_minutes分後まで延長する
+-- channel_auctionのものはチャンネルの既定値で、auction_infoには出品時に確定した値が入る
+ALTER TABLE channel_auction
+    ADD COLUMN extension_window INTEGER CHECK (extension_window > 0),
+    ADD COLUMN extension_minutes INTEGER CHECK (extension_minutes > 0),
+    ADD CONSTRAINT channel_auction_extension_pair CHECK ((extension_window IS NULL) = (extension_minutes IS NULL));
+
+ALTER TABLE auction_info
+    ADD COLUMN extension_window INTEGER CHECK (extension_window > 0),
+    ADD COLUMN extension_minutes INTEGER CHECK (extension_minutes > 0),
+    ADD CONSTRAINT auction_info_extension_pair CHECK ((extension_window IS NULL) = (extension_minutes IS NULL));
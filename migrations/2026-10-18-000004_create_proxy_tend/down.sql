@@ -0,0 +1 @@
+DROP TABLE proxy_tend;
@@ -0,0 +1,10 @@
+-- 自動入札の上限額。入札者本人以外には公開しない
+CREATE TABLE proxy_tend (
+    id SERIAL PRIMARY KEY,
+    auction_id INTEGER NOT NULL REFERENCES auction_info (id) ON DELETE CASCADE,
+    tender_id BIGINT NOT NULL,
+    max_price INTEGER NOT NULL,
+    tender_name TEXT NOT NULL,
+    created_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    UNIQUE (auction_id, tender_id)
+);
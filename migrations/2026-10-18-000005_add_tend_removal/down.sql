@@ -0,0 +1,4 @@
+ALTER TABLE tend_history
+    DROP COLUMN removed_at,
+    DROP COLUMN removed_by,
+    DROP COLUMN remove_reason;
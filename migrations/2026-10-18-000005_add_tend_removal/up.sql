@@ -0,0 +1,5 @@
+-- 取り消された入札も履歴として残し、誰がいつなぜ取り消したかを記録する
+ALTER TABLE tend_history
+    ADD COLUMN removed_at TIMESTAMP,
+    ADD COLUMN removed_by BIGINT,
+    ADD COLUMN remove_reason TEXT;
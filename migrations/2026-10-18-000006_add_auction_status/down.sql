@@ -0,0 +1,5 @@
+ALTER TABLE auction_info
+    DROP COLUMN status,
+    DROP COLUMN winner_id,
+    DROP COLUMN final_price,
+    DROP COLUMN finished_at;
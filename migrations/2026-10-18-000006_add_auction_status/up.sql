@@ -0,0 +1,32 @@
+-- オークションの状態
+-- active: 開催中, sold: 落札, unsold: 入札なし等で不成立, bought_out: 即決, cancelled: 取り消し
+ALTER TABLE auction_info
+    ADD COLUMN status TEXT NOT NULL DEFAULT 'active'
+        CHECK (status IN ('active', 'sold', 'unsold', 'bought_out', 'cancelled')),
+    ADD COLUMN winner_id BIGINT,
+    ADD COLUMN final_price INTEGER,
+    ADD COLUMN finished_at TIMESTAMP;
+
+-- 既に終了しているオークションは入札履歴から結果を埋める
+WITH last_tend AS (
+    SELECT DISTINCT ON (auction_id) auction_id, tender_id, price, tended_at
+    FROM tend_history
+    WHERE removed_at IS NULL
+    ORDER BY auction_id, id DESC
+)
+UPDATE auction_info info
+SET status = CASE
+        WHEN last_tend.auction_id IS NULL THEN 'unsold'
+        WHEN info.bin_price IS NOT NULL AND last_tend.price >= info.bin_price THEN 'bought_out'
+        ELSE 'sold'
+    END,
+    winner_id = last_tend.tender_id,
+    final_price = last_tend.price,
+    finished_at = CASE
+        WHEN info.bin_price IS NOT NULL AND last_tend.price >= info.bin_price THEN last_tend.tended_at
+        ELSE info.end_time
+    END
+FROM auction_info target
+LEFT JOIN last_tend ON last_tend.auction_id = target.id
+WHERE info.id = target.id
+  AND NOT EXISTS (SELECT 1 FROM channel_auction WHERE channel_auction.auction = info.id);
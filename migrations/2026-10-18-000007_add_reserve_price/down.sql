@@ -0,0 +1 @@
+ALTER TABLE auction_info DROP COLUMN reserve_price;
@@ -0,0 +1,2 @@
+-- 非公開の最低落札価格。これに満たない入札しかなければ不成立になる
+ALTER TABLE auction_info ADD COLUMN reserve_price INTEGER;
@@ -0,0 +1,3 @@
+DROP TABLE dutch_schedule;
+
+ALTER TABLE auction_info DROP COLUMN auction_type;
@@ -0,0 +1,14 @@
+-- english: 通常の競り上げ式, dutch: 価格が時間とともに下がり、最初に!buyした人が落札する
+ALTER TABLE auction_info
+    ADD COLUMN auction_type TEXT NOT NULL DEFAULT 'english'
+        CONSTRAINT auction_info_auction_type_check CHECK (auction_type IN ('english', 'dutch'));
+
+-- ダッチオークションの値下げスケジュール。開始価格はauction_info.start_price
+CREATE TABLE dutch_schedule (
+    auction_id INTEGER PRIMARY KEY REFERENCES auction_info (id) ON DELETE CASCADE,
+    floor_price INTEGER NOT NULL CHECK (floor_price > 0),
+    step INTEGER NOT NULL CHECK (step > 0),
+    interval_minutes INTEGER NOT NULL CHECK (interval_minutes > 0),
+    current_price INTEGER NOT NULL,
+    next_drop_at TIMESTAMP NOT NULL
+);
@@ -0,0 +1,5 @@
+ALTER TABLE auction_info DROP COLUMN second_price;
+
+ALTER TABLE auction_info
+    DROP CONSTRAINT auction_info_auction_type_check,
+    ADD CONSTRAINT auction_info_auction_type_check CHECK (auction_type IN ('english', 'dutch'));
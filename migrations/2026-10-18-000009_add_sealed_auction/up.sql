@@ -0,0 +1,7 @@
+-- sealed: 入札はBotへのDMで受け付け、終了時に全順位を公開する
+ALTER TABLE auction_info
+    DROP CONSTRAINT auction_info_auction_type_check,
+    ADD CONSTRAINT auction_info_auction_type_check CHECK (auction_type IN ('english', 'dutch', 'sealed'));
+
+-- TRUEなら落札者は2番目の入札額+最低入札単位を支払う(セカンドプライス)
+ALTER TABLE auction_info ADD COLUMN second_price BOOLEAN NOT NULL DEFAULT FALSE;
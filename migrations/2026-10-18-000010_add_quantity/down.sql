@@ -0,0 +1,3 @@
+ALTER TABLE tend_history DROP COLUMN won_quantity, DROP COLUMN quantity;
+
+ALTER TABLE auction_info DROP COLUMN quantity;
@@ -0,0 +1,7 @@
+-- 同じ物を複数個まとめて出品する場合の個数。入札額は1個あたりの価格
+ALTER TABLE auction_info ADD COLUMN quantity INTEGER NOT NULL DEFAULT 1 CHECK (quantity > 0);
+
+-- quantity: 入札で希望した個数, won_quantity: 終了時に落札できた個数(落札していなければNULL)
+ALTER TABLE tend_history
+    ADD COLUMN quantity INTEGER NOT NULL DEFAULT 1 CHECK (quantity > 0),
+    ADD COLUMN won_quantity INTEGER;
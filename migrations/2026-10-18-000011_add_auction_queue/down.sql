@@ -0,0 +1,10 @@
+DROP INDEX auction_info_queue_idx;
+
+UPDATE auction_info SET status = 'cancelled' WHERE status = 'queued';
+
+ALTER TABLE auction_info DROP COLUMN duration_minutes;
+
+ALTER TABLE auction_info
+    DROP CONSTRAINT auction_info_status_check,
+    ADD CONSTRAINT auction_info_status_check
+        CHECK (status IN ('active', 'sold', 'unsold', 'bought_out', 'cancelled'));
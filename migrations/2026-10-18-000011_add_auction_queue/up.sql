@@ -0,0 +1,10 @@
+-- queued: チャンネルで別のオークションが開催中のため順番待ちしている
+ALTER TABLE auction_info
+    DROP CONSTRAINT auction_info_status_check,
+    ADD CONSTRAINT auction_info_status_check
+        CHECK (status IN ('queued', 'active', 'sold', 'unsold', 'bought_out', 'cancelled'));
+
+-- 順番待ちのオークションの開催期間(分)。開始した時刻にこれを足して終了日時とする
+ALTER TABLE auction_info ADD COLUMN duration_minutes INTEGER CHECK (duration_minutes > 0);
+
+CREATE INDEX auction_info_queue_idx ON auction_info (channel_id, id) WHERE status = 'queued';
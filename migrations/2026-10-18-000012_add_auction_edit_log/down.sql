@@ -0,0 +1 @@
+DROP TABLE auction_edit_log;
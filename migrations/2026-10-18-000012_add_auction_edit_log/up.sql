@@ -0,0 +1,12 @@
+-- 出品者による!editの記録
+CREATE TABLE auction_edit_log (
+    id SERIAL PRIMARY KEY,
+    auction_id INTEGER NOT NULL REFERENCES auction_info (id) ON DELETE CASCADE,
+    editor_id BIGINT NOT NULL,
+    field TEXT NOT NULL,
+    old_value TEXT NOT NULL,
+    new_value TEXT NOT NULL,
+    edited_at TIMESTAMP NOT NULL
+);
+
+CREATE INDEX auction_edit_log_auction_id_idx ON auction_edit_log (auction_id);
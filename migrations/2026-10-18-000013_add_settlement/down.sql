@@ -0,0 +1,3 @@
+ALTER TABLE channel_auction DROP COLUMN settlement_remind_hours;
+
+DROP TABLE settlement;
@@ -0,0 +1,25 @@
+-- 落札後の取引の記録。落札者ごとに1件作る
+-- delivered_at: 出品者が受け渡しを確認した日時, paid_at: 落札者が支払いを確認した日時
+CREATE TABLE settlement (
+    id SERIAL PRIMARY KEY,
+    auction_id INTEGER NOT NULL REFERENCES auction_info (id) ON DELETE CASCADE,
+    seller_id BIGINT NOT NULL,
+    winner_id BIGINT NOT NULL,
+    price INTEGER NOT NULL,
+    quantity INTEGER NOT NULL,
+    created_at TIMESTAMP NOT NULL,
+    remind_hours INTEGER NOT NULL,
+    delivered_at TIMESTAMP,
+    paid_at TIMESTAMP,
+    reminded_count INTEGER NOT NULL DEFAULT 0,
+    last_reminded_at TIMESTAMP,
+    disputed_by BIGINT,
+    dispute_reason TEXT,
+    disputed_at TIMESTAMP
+);
+
+CREATE INDEX settlement_open_idx ON settlement (created_at) WHERE delivered_at IS NULL OR paid_at IS NULL;
+
+-- 取引が完了していない場合にリマインドを送るまでの時間
+ALTER TABLE channel_auction
+    ADD COLUMN settlement_remind_hours INTEGER NOT NULL DEFAULT 24 CHECK (settlement_remind_hours > 0);
@@ -0,0 +1,6 @@
+DROP TABLE second_chance_offer;
+
+DROP INDEX settlement_open_idx;
+CREATE INDEX settlement_open_idx ON settlement (created_at) WHERE delivered_at IS NULL OR paid_at IS NULL;
+
+ALTER TABLE settlement DROP COLUMN defaulted_at;
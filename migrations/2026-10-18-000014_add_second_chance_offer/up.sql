@@ -0,0 +1,23 @@
+-- 落札者が取引を放棄した日時。放棄された取引はリマインドの対象から外す
+ALTER TABLE settlement ADD COLUMN defaulted_at TIMESTAMP;
+
+DROP INDEX settlement_open_idx;
+CREATE INDEX settlement_open_idx ON settlement (created_at) WHERE (delivered_at IS NULL OR paid_at IS NULL) AND defaulted_at IS NULL;
+
+-- 落札者が放棄した出品物を次点の入札者に提示する
+-- settlement_id: 放棄された取引, accepted_settlement_id: 承諾されて作られた取引
+CREATE TABLE second_chance_offer (
+    id SERIAL PRIMARY KEY,
+    settlement_id INTEGER NOT NULL REFERENCES settlement (id) ON DELETE CASCADE,
+    auction_id INTEGER NOT NULL REFERENCES auction_info (id) ON DELETE CASCADE,
+    bidder_id BIGINT NOT NULL,
+    price INTEGER NOT NULL,
+    quantity INTEGER NOT NULL,
+    offered_at TIMESTAMP NOT NULL,
+    expires_at TIMESTAMP NOT NULL,
+    status TEXT NOT NULL DEFAULT 'pending' CONSTRAINT second_chance_offer_status_check CHECK (status IN ('pending', 'accepted', 'declined', 'expired')),
+    responded_at TIMESTAMP,
+    accepted_settlement_id INTEGER REFERENCES settlement (id) ON DELETE SET NULL
+);
+
+CREATE INDEX second_chance_offer_pending_idx ON second_chance_offer (expires_at) WHERE status = 'pending';
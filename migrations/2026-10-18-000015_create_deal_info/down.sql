@@ -0,0 +1,2 @@
+DROP TABLE deal_info;
+DROP TABLE channel_deal;
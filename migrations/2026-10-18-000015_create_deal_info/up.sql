@@ -0,0 +1,23 @@
+-- 取引チャンネル。固定価格の出品を扱い、1チャンネルに複数の出品を同時に掲載できる
+CREATE TABLE channel_deal (
+    channel BIGINT PRIMARY KEY
+);
+
+-- 固定価格の出品
+-- open: 掲載中, sold: 購入済み, expired: 期限切れ
+CREATE TABLE deal_info (
+    id SERIAL PRIMARY KEY,
+    channel_id BIGINT NOT NULL REFERENCES channel_deal (channel) ON DELETE CASCADE,
+    owner_id BIGINT NOT NULL,
+    item TEXT NOT NULL,
+    unit TEXT NOT NULL,
+    price INTEGER NOT NULL CHECK (price > 0),
+    end_time TIMESTAMP NOT NULL,
+    notice TEXT NOT NULL,
+    embed_id BIGINT,
+    status TEXT NOT NULL DEFAULT 'open' CHECK (status IN ('open', 'sold', 'expired')),
+    buyer_id BIGINT,
+    finished_at TIMESTAMP
+);
+
+CREATE INDEX deal_info_open_idx ON deal_info (channel_id, end_time) WHERE status = 'open';
@@ -0,0 +1,3 @@
+ALTER TABLE channel_auction DROP COLUMN guild_id;
+
+DROP TABLE unit_definition;
@@ -0,0 +1,14 @@
+-- サーバーごとの価格の単位。既定の単位(LC=3456, st=64)に重ねて使い、同じ表示名の単位は置き換える
+-- currency: 対象の通貨(オークションの単位)。空文字列はサーバー全体の定義で、通貨ごとの定義が優先される
+CREATE TABLE unit_definition (
+    id SERIAL PRIMARY KEY,
+    guild_id BIGINT NOT NULL,
+    currency TEXT NOT NULL DEFAULT '',
+    label TEXT NOT NULL,
+    aliases TEXT[] NOT NULL DEFAULT '{}',
+    multiplier INTEGER NOT NULL CHECK (multiplier > 0),
+    UNIQUE (guild_id, currency, label)
+);
+
+-- 単位の定義を引くためのオークションチャンネルのサーバー
+ALTER TABLE channel_auction ADD COLUMN guild_id BIGINT;
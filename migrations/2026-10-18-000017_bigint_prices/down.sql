@@ -0,0 +1,22 @@
+-- INTEGERに収まらない価格があると失敗する
+ALTER TABLE deal_info ALTER COLUMN price TYPE INTEGER;
+
+ALTER TABLE second_chance_offer ALTER COLUMN price TYPE INTEGER;
+
+ALTER TABLE settlement ALTER COLUMN price TYPE INTEGER;
+
+ALTER TABLE dutch_schedule
+    ALTER COLUMN floor_price TYPE INTEGER,
+    ALTER COLUMN step TYPE INTEGER,
+    ALTER COLUMN current_price TYPE INTEGER;
+
+ALTER TABLE proxy_tend ALTER COLUMN max_price TYPE INTEGER;
+
+ALTER TABLE tend_history ALTER COLUMN price TYPE INTEGER;
+
+ALTER TABLE auction_info
+    ALTER COLUMN start_price TYPE INTEGER,
+    ALTER COLUMN bin_price TYPE INTEGER,
+    ALTER COLUMN min_increment TYPE INTEGER,
+    ALTER COLUMN final_price TYPE INTEGER,
+    ALTER COLUMN reserve_price TYPE INTEGER;
@@ -0,0 +1,22 @@
+-- 価格を64bitにする。LC単位の大きな価格でも桁あふれしないようにする
+ALTER TABLE auction_info
+    ALTER COLUMN start_price TYPE BIGINT,
+    ALTER COLUMN bin_price TYPE BIGINT,
+    ALTER COLUMN min_increment TYPE BIGINT,
+    ALTER COLUMN final_price TYPE BIGINT,
+    ALTER COLUMN reserve_price TYPE BIGINT;
+
+ALTER TABLE tend_history ALTER COLUMN price TYPE BIGINT;
+
+ALTER TABLE proxy_tend ALTER COLUMN max_price TYPE BIGINT;
+
+ALTER TABLE dutch_schedule
+    ALTER COLUMN floor_price TYPE BIGINT,
+    ALTER COLUMN step TYPE BIGINT,
+    ALTER COLUMN current_price TYPE BIGINT;
+
+ALTER TABLE settlement ALTER COLUMN price TYPE BIGINT;
+
+ALTER TABLE second_chance_offer ALTER COLUMN price TYPE BIGINT;
+
+ALTER TABLE deal_info ALTER COLUMN price TYPE BIGINT;
@@ -0,0 +1,31 @@
+-- UTCの日時に戻す。終了日時・掲載期限は日本時間に戻す
+ALTER TABLE deal_info
+    ALTER COLUMN end_time TYPE TIMESTAMP USING end_time AT TIME ZONE 'Asia/Tokyo',
+    ALTER COLUMN finished_at TYPE TIMESTAMP USING finished_at AT TIME ZONE 'UTC';
+
+ALTER TABLE second_chance_offer
+    ALTER COLUMN offered_at TYPE TIMESTAMP USING offered_at AT TIME ZONE 'UTC',
+    ALTER COLUMN expires_at TYPE TIMESTAMP USING expires_at AT TIME ZONE 'UTC',
+    ALTER COLUMN responded_at TYPE TIMESTAMP USING responded_at AT TIME ZONE 'UTC';
+
+ALTER TABLE settlement
+    ALTER COLUMN created_at TYPE TIMESTAMP USING created_at AT TIME ZONE 'UTC',
+    ALTER COLUMN delivered_at TYPE TIMESTAMP USING delivered_at AT TIME ZONE 'UTC',
+    ALTER COLUMN paid_at TYPE TIMESTAMP USING paid_at AT TIME ZONE 'UTC',
+    ALTER COLUMN last_reminded_at TYPE TIMESTAMP USING last_reminded_at AT TIME ZONE 'UTC',
+    ALTER COLUMN disputed_at TYPE TIMESTAMP USING disputed_at AT TIME ZONE 'UTC',
+    ALTER COLUMN defaulted_at TYPE TIMESTAMP USING defaulted_at AT TIME ZONE 'UTC';
+
+ALTER TABLE auction_edit_log ALTER COLUMN edited_at TYPE TIMESTAMP USING edited_at AT TIME ZONE 'UTC';
+
+ALTER TABLE dutch_schedule ALTER COLUMN next_drop_at TYPE TIMESTAMP USING next_drop_at AT TIME ZONE 'UTC';
+
+ALTER TABLE proxy_tend ALTER COLUMN created_at TYPE TIMESTAMP USING created_at AT TIME ZONE 'UTC';
+
+ALTER TABLE tend_history
+    ALTER COLUMN tended_at TYPE TIMESTAMP USING tended_at AT TIME ZONE 'UTC',
+    ALTER COLUMN removed_at TYPE TIMESTAMP USING removed_at AT TIME ZONE 'UTC';
+
+ALTER TABLE auction_info
+    ALTER COLUMN end_time TYPE TIMESTAMP USING end_time AT TIME ZONE 'Asia/Tokyo',
+    ALTER COLUMN finished_at TYPE TIMESTAMP USING finished_at AT TIME ZONE 'UTC';
@@ -0,0 +1,33 @@
+-- 日時をUTCのtimestamptzで保存する
+-- これまでの値はサーバーのローカル時刻(HerokuではUTC)で保存されていたため、UTCとして変換する
+-- ただし終了日時・掲載期限は利用者が日本時間で入力した値をそのまま保存していたため、日本時間として変換する
+ALTER TABLE auction_info
+    ALTER COLUMN end_time TYPE TIMESTAMPTZ USING end_time AT TIME ZONE 'Asia/Tokyo',
+    ALTER COLUMN finished_at TYPE TIMESTAMPTZ USING finished_at AT TIME ZONE 'UTC';
+
+ALTER TABLE tend_history
+    ALTER COLUMN tended_at TYPE TIMESTAMPTZ USING tended_at AT TIME ZONE 'UTC',
+    ALTER COLUMN removed_at TYPE TIMESTAMPTZ USING removed_at AT TIME ZONE 'UTC';
+
+ALTER TABLE proxy_tend ALTER COLUMN created_at TYPE TIMESTAMPTZ USING created_at AT TIME ZONE 'UTC';
+
+ALTER TABLE dutch_schedule ALTER COLUMN next_drop_at TYPE TIMESTAMPTZ USING next_drop_at AT TIME ZONE 'UTC';
+
+ALTER TABLE auction_edit_log ALTER COLUMN edited_at TYPE TIMESTAMPTZ USING edited_at AT TIME ZONE 'UTC';
+
+ALTER TABLE settlement
+    ALTER COLUMN created_at TYPE TIMESTAMPTZ USING created_at AT TIME ZONE 'UTC',
+    ALTER COLUMN delivered_at TYPE TIMESTAMPTZ USING delivered_at AT TIME ZONE 'UTC',
+    ALTER COLUMN paid_at TYPE TIMESTAMPTZ USING paid_at AT TIME ZONE 'UTC',
+    ALTER COLUMN last_reminded_at TYPE TIMESTAMPTZ USING last_reminded_at AT TIME ZONE 'UTC',
+    ALTER COLUMN disputed_at TYPE TIMESTAMPTZ USING disputed_at AT TIME ZONE 'UTC',
+    ALTER COLUMN defaulted_at TYPE TIMESTAMPTZ USING defaulted_at AT TIME ZONE 'UTC';
+
+ALTER TABLE second_chance_offer
+    ALTER COLUMN offered_at TYPE TIMESTAMPTZ USING offered_at AT TIME ZONE 'UTC',
+    ALTER COLUMN expires_at TYPE TIMESTAMPTZ USING expires_at AT TIME ZONE 'UTC',
+    ALTER COLUMN responded_at TYPE TIMESTAMPTZ USING responded_at AT TIME ZONE 'UTC';
+
+ALTER TABLE deal_info
+    ALTER COLUMN end_time TYPE TIMESTAMPTZ USING end_time AT TIME ZONE 'Asia/Tokyo',
+    ALTER COLUMN finished_at TYPE TIMESTAMPTZ USING finished_at AT TIME ZONE 'UTC';
@@ -0,0 +1,3 @@
+ALTER TABLE auction_info DROP CONSTRAINT auction_info_reserve_price_check;
+
+ALTER TABLE proxy_tend DROP CONSTRAINT proxy_tend_max_price_check;
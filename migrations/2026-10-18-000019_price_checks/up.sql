@@ -0,0 +1,4 @@
+-- 他の価格の列と同様に、自動入札の上限額と最低落札価格も正の値に限る
+ALTER TABLE proxy_tend ADD CONSTRAINT proxy_tend_max_price_check CHECK (max_price > 0);
+
+ALTER TABLE auction_info ADD CONSTRAINT auction_info_reserve_price_check CHECK (reserve_price > 0);
@@ -0,0 +1,6 @@
+-- 登録が解除されたチャンネルの出品は削除される
+DELETE FROM deal_info WHERE channel_id NOT IN (SELECT channel FROM channel_deal);
+ALTER TABLE deal_info
+    ADD CONSTRAINT deal_info_channel_id_fkey FOREIGN KEY (channel_id) REFERENCES channel_deal (channel) ON DELETE CASCADE;
+
+ALTER TABLE channel_deal DROP COLUMN guild_id;
@@ -0,0 +1,5 @@
+-- 取引チャンネルもサーバーごとに一覧できるよう、登録したサーバーを記録する
+ALTER TABLE channel_deal ADD COLUMN guild_id BIGINT;
+
+-- オークションチャンネルと同様、登録を解除しても過去の出品の記録は残す
+ALTER TABLE deal_info DROP CONSTRAINT deal_info_channel_id_fkey;
@@ -8,7 +8,11 @@ use serenity::framework::standard::{
 use crate::utils::*;
 use crate::schema::{
     auction_info::dsl::auction_info as auction_info_table,
-    channel_auction::dsl::channel_auction as channel_auction_table,
+    channel_auction::dsl::{channel_auction as channel_auction_table, extension_window, extension_minutes, settlement_remind_hours},
+    tend_history::dsl::tend_history as tend_history_table,
+    auction_edit_log::dsl::auction_edit_log as auction_edit_log_table,
+    deal_info::dsl::deal_info as deal_info_table,
+    unit_definition::dsl::unit_definition as unit_definition_table,
 };
 use crate::models::*;
 use diesel;
@@ -40,6 +44,22 @@ async fn select(ctx: &Context, msg: &Message, mut args: Args) -> CommandResult {
             let result: Vec<ChannelAuction> = channel_auction_table.load(&conn)?;
             result.iter().map(|row| format!("{:?}", row)).collect::<Vec<_>>().join("\n")
         },
+        "tend_history" => {
+            let result: Vec<TendHistory> = tend_history_table.load(&conn)?;
+            result.iter().map(|row| format!("{:?}", row)).collect::<Vec<_>>().join("\n")
+        },
+        "unit_definition" => {
+            let result: Vec<UnitDefinition> = unit_definition_table.load(&conn)?;
+            result.iter().map(|row| format!("{:?}", row)).collect::<Vec<_>>().join("\n")
+        },
+        "deal_info" => {
+            let result: Vec<DealInfo> = deal_info_table.load(&conn)?;
+            result.iter().map(|row| format!("{:?}", row)).collect::<Vec<_>>().join("\n")
+        },
+        "auction_edit_log" => {
+            let result: Vec<AuctionEditLog> = auction_edit_log_table.load(&conn)?;
+            result.iter().map(|row| format!("{:?}", row)).collect::<Vec<_>>().join("\n")
+        },
         _ => "設定されていないテーブルです".to_string(),
     };
     msg.channel_id.say(&ctx.http, result).await?;
@@ -47,7 +67,58 @@ async fn select(ctx: &Context, msg: &Message, mut args: Args) -> CommandResult {
 }
 
 
+// このチャンネルで出品時に「デフォルト」を選んだ場合の延長設定を変更する
+#[command]
+async fn auction_extension(ctx: &Context, msg: &Message, args: Args) -> CommandResult {
+    let conn = ctx.get_connection().await;
+
+    let content = args.message().trim();
+    let (window, minutes) = if content == "なし" {
+        (None, None)
+    } else if let Some((window, minutes)) = formats::end_extension(content) {
+        (Some(window), Some(minutes))
+    } else {
+        msg.channel_id.say(&ctx.http, "`!auction_extension 終了何分前以降の入札で延長するか 何分延長するか` または `!auction_extension なし` の形式で入力してください").await?;
+        return Ok(());
+    };
+
+    let result = diesel::update(channel_auction_table.find(msg.channel_id.0 as i64))
+        .set((extension_window.eq(window), extension_minutes.eq(minutes))).execute(&conn)?;
+    if result == 0 {
+        msg.channel_id.say(&ctx.http, "このチャンネルはオークションチャンネルではありません").await?;
+    } else {
+        let text = EndExtension::from_columns(window, minutes).map_or_else(|| "なし".into(), |extension| extension.describe());
+        msg.channel_id.say(&ctx.http, format!("このチャンネルの延長設定を変更しました: {}", text)).await?;
+    }
+    Ok(())
+}
+
+
+// このチャンネルで落札された取引が完了していない場合に、リマインドを送るまでの時間を変更する
+#[command]
+async fn settlement_reminder(ctx: &Context, msg: &Message, args: Args) -> CommandResult {
+    let conn = ctx.get_connection().await;
+
+    let hours = match args.message().trim().parse::<i32>() {
+        Ok(hours) if hours > 0 => hours,
+        _ => {
+            msg.channel_id.say(&ctx.http, "`!settlement_reminder 時間` の形式で、1以上の整数で入力してください").await?;
+            return Ok(());
+        }
+    };
+
+    let result = diesel::update(channel_auction_table.find(msg.channel_id.0 as i64))
+        .set(settlement_remind_hours.eq(hours)).execute(&conn)?;
+    if result == 0 {
+        msg.channel_id.say(&ctx.http, "このチャンネルはオークションチャンネルではありません").await?;
+    } else {
+        msg.channel_id.say(&ctx.http, format!("取引のリマインドを{}時間ごとに送るように変更しました", hours)).await?;
+    }
+    Ok(())
+}
+
+
 #[group]
-#[commands(execute_sql, select)]
+#[commands(execute_sql, select, auction_extension, settlement_reminder)]
 #[required_permissions(ADMINISTRATOR)]
 pub struct AdminOnly;
\ No newline at end of file
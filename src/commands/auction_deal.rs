@@ -6,14 +6,22 @@ use serenity::framework::standard::{
     CommandResult,
 };
 use crate::schema::{
-    auction_info::dsl::{auction_info, id as auction_id_col, embed_id},
-    channel_auction::dsl::{channel_auction, channel as channel_col, auction as auction_col},
+    auction_info::dsl::{
+        auction_info, id as auction_id_col, embed_id, status as status_col, duration_minutes as duration_minutes_col,
+        channel_id as info_channel_col,
+    },
+    channel_auction::dsl::{channel_auction, channel as channel_col, auction as auction_col, guild_id as guild_id_col},
+    dutch_schedule::dsl::dutch_schedule,
 };
 use crate::utils::*;
 use crate::models::*;
 use diesel;
 use diesel::prelude::*;
-use chrono::{Local, Duration, NaiveDate, Datelike, Timelike};
+use chrono::{DateTime, Duration, NaiveDate, Datelike, Timelike, Utc};
+
+const NOT_AUCTION_CHANNEL: &str = "このチャンネルはオークションチャンネルではありません\n\
+    チャンネルの管理権限を持つ人が`!auction_channel add`でオークションチャンネルに登録できます";
+pub(super) const PRICE_FORMAT_HELP: &str = "**※次のように入力してください。【〇LC+△ST+□】 or 【〇ST+△】 or 【△】 ex.1lc+1st+1 or 1st+1 or 32 or 1.5lc**";
 
 macro_rules! unwrap_or_return {
     ($result:expr) => {
@@ -25,6 +33,75 @@ macro_rules! unwrap_or_return {
     }
 }
 
+// 日時または期間の入力を読み取り、(日時, 表示用の文字列)を返す
+// 入力は設定されたタイムゾーンの日時として読み、UTCにして返す
+pub(super) fn read_time(content: &str) -> Result<(DateTime<Utc>, String), String> {
+    let now = clock::local_now();
+
+    let time = if let Some((year, month, day, hour, minute)) = formats::datetime(content) {
+        if !(2000 <= year && year <= 3000) {
+            return Err("年は2000~3000の範囲で指定してください".into());
+        }
+        if !(1 <= month && month <= 12) {
+            return Err("存在しない月です".into());
+        }
+        if !(1 <= day && day <= formats::last_day(year, month)) {
+            return Err("存在しない日です".into());
+        }
+        let date = NaiveDate::from_ymd(year, month, day);
+        let datetime;
+        if (hour, minute) == (24, 00) {
+            datetime = date.and_hms(0, 0, 0) + Duration::days(1);
+        } else if !(hour < 24 && minute < 60) {
+            return Err("範囲外の時刻です".into());
+        } else {
+            datetime = date.and_hms(hour, minute, 0);
+        }
+        (datetime, year, month, day, hour, minute)
+
+    } else if let Some(duration) = formats::duration(content) {
+        let time = formats::add_months(now, duration.0) + duration.1;
+        (time, time.year(), time.month(), time.day(), time.hour(), time.minute())
+
+    } else if let Some(time) = formats::natural_datetime(content, now) {
+        (time, time.year(), time.month(), time.day(), time.hour(), time.minute())
+
+    } else {
+        let year = now.year();
+        return Err(format!("時間の書式が正しくありません\n\n
+        例 {0}年5月14日の午後8時に終了したい場合：\n**{0}/05/14-20:00**と入力してください。\n\n
+        例 1カ月2週間3日4時間5分後に終了したい場合:\n**1M2w3d4h5m**と入力してください。\n\n
+        `明日20時`、`5/14 20:00`、`土曜21時`、`3日後`、`3日4時間`のようにも入力できます。\n\n", year));
+    };
+
+    let end_time = clock::from_local(&time.0).ok_or_else(|| "存在しない時刻です".to_string())?;
+    Ok((end_time, format!("{:0>4}/{:0>2}/{:0>2} {:0>2}:{:0>2}", time.1, time.2, time.3, time.4, time.5)))
+}
+
+// オークションの終了日時の入力を読み取る。開催期間は12時間より長く8週間未満
+pub(super) fn parse_end_time(content: &str) -> Result<(DateTime<Utc>, String), String> {
+    let (end_time, end_time_txt) = read_time(content)?;
+    let duration = end_time - Utc::now();
+    if duration <= Duration::zero() {
+        Err("終了時刻を現在時刻以前にすることはできません。".into())
+    } else if duration <= Duration::hours(12) {
+        Err("開催期間を12時間以下にすることはできません。".into())
+    } else if duration >= Duration::weeks(8) {
+        Err("2ヶ月以上にわたるオークションはできません。".into())
+    } else {
+        Ok((end_time, end_time_txt))
+    }
+}
+
+// 価格の入力を読み取る。形式が正しくなければ、どこが誤っているかと入力例を返す
+// 大きすぎる値は形式の問題ではないので入力例は付けない
+pub(super) fn read_price(content: &str, units: &UnitSystem) -> Result<i64, String> {
+    units.parse(content).map_err(|error| match error {
+        formats::PriceError::TooLarge => error.to_string(),
+        _ => format!("{}\n{}", error, PRICE_FORMAT_HELP),
+    })
+}
+
 #[command]
 async fn start(ctx: &Context, msg: &Message) -> CommandResult {
     let conn = ctx.get_connection().await;
@@ -32,15 +109,24 @@ async fn start(ctx: &Context, msg: &Message) -> CommandResult {
     let channel_id = msg.channel_id.0 as i64;
 
     let result = channel_auction.filter(channel_col.eq(channel_id)).get_results::<ChannelAuction>(&conn)?;
-    if let [ChannelAuction { auction, .. }] = result[..] {
+    let channel_extension = if let [ChannelAuction { auction, extension_window, extension_minutes, guild_id, .. }] = result[..] {
+        // 単位の定義を引けるよう、登録コマンド以前に登録されたチャンネルにはサーバーを記録しておく
+        if guild_id.is_none() {
+            diesel::update(channel_auction.find(channel_id))
+                .set(guild_id_col.eq(msg.guild_id.map(|guild_id| guild_id.0 as i64)))
+                .execute(&conn)?;
+        }
+        // 開催中でも出品はでき、順番待ちに入る
         if let Some(auction_id) = auction {
-            msg.channel_id.say(&ctx.http, format!("既にオークションが開催されています (id:{})", auction_id)).await?;
-            return Ok(());
+            msg.channel_id.say(&ctx.http, format!("既にオークションが開催されています (id:{})\n\
+                このまま出品すると順番待ちに入り、前のオークションが終わり次第開始されます。\
+                その場合、終了日時は開始時刻から出品時に指定した期間だけ後になります。", auction_id)).await?;
         }
+        EndExtension::from_columns(extension_window, extension_minutes)
     } else {
-        msg.channel_id.say(&ctx.http, "このチャンネルはオークションチャンネルではありません").await?;
+        msg.channel_id.say(&ctx.http, NOT_AUCTION_CHANNEL).await?;
         return Ok(());
-    }
+    };
     
     msg.channel_id.send_message(ctx, |m| {
         m.embed(|e| {
@@ -54,6 +140,7 @@ async fn start(ctx: &Context, msg: &Message) -> CommandResult {
             Ok(content.to_string())
         }
     }).await);
+    let units = unit_manager::for_guild(&conn, msg.guild_id, &unit)?;
 
     msg.channel_id.send_message(ctx, |m| {
         m.embed(|e| {
@@ -70,108 +157,245 @@ async fn start(ctx: &Context, msg: &Message) -> CommandResult {
 
     msg.channel_id.send_message(ctx, |m| {
         m.embed(|e| {
-            e.description(
-                "開始価格を入力してください。
-                **※次のように入力してください。【〇LC+△ST+□】 or　【〇ST+△】 or 【△】 ex.1lc+1st+1 or 1st+1 or 32**"
-            ).color(0xffaf60)
+            e.description("オークションの形式を選んでください。\n
+            `通常`: 入札によって価格が上がっていく形式\n
+            `ダッチ`: 一定時間ごとに価格が下がっていき、最初に`!buy`した人が落札する形式\n
+            `封印`: 入札はBotへのDMで行い、終了時まで誰の入札額も公開されない形式").color(0xffaf60)
         })
     }).await?;
-    let start_price = unwrap_or_return!(discord_helper::await_right_reply(ctx, msg, |content| {
-        if let Some(price) = formats::stack_to_int(content) {
-            if price == 0 {
-                Err("開始価格を0にすることはできません".into())
-            } else {
-                Ok(price)
-            }
-        } else {
-            Err("価格の形式が正しくありません\n**※次のように入力してください。【〇LC+△ST+□】 or 【〇ST+△】 or 【△】 ex.1lc+1st+1 or 1st+1 or 32**".into())
+    let auction_type = unwrap_or_return!(discord_helper::await_right_reply(ctx, msg, |content| {
+        match content.trim() {
+            "通常" => Ok(AuctionType::English),
+            "ダッチ" => Ok(AuctionType::Dutch),
+            "封印" => Ok(AuctionType::Sealed),
+            _ => Err("`通常`、`ダッチ`、`封印`のいずれかで答えてください".into()),
         }
     }).await);
 
+    let quantity = if auction_type == AuctionType::English {
+        msg.channel_id.send_message(ctx, |m| {
+            m.embed(|e| {
+                e.description("出品する個数を入力してください。\n
+                2個以上の場合、入札額は1個あたりの価格になり、入札額の高い順に個数分が落札されます。
+                入札者は`!tend 価格 x個数`の形式で個数を指定します。").color(0xffaf60)
+            })
+        }).await?;
+        unwrap_or_return!(discord_helper::await_right_reply(ctx, msg, |content| {
+            match content.trim().trim_end_matches('個').parse::<i32>() {
+                Ok(quantity) if quantity >= 1 => Ok(quantity),
+                _ => Err("個数は1以上の整数で入力してください".into()),
+            }
+        }).await)
+    } else {
+        1
+    };
+
     msg.channel_id.send_message(ctx, |m| {
         m.embed(|e| {
-            e.description(
-                "即決価格を入力してください。\n
-                **※次のように入力してください。【〇LC+△ST+□】 or　【〇ST+△】 or 【△】 ex.1lc+1st+1 or 1st+1 or 32**\n
-                ない場合は`なし`とお書きください。").color(0xffaf60)
+            e.description(format!("開始価格を入力してください。\n{}", PRICE_FORMAT_HELP)).color(0xffaf60)
         })
     }).await?;
-    let bin_price = unwrap_or_return!(discord_helper::await_right_reply(ctx, msg, |content| {
-        if content == "なし" {
-            Ok(None)
-        } else if let Some(price) = formats::stack_to_int(content) {
-            if price == start_price {
-                Err("即決価格が開始価格と等しいです。(価格が決まっているのであれば取引チャンネルをお使いください。)".into())
-            } else if price < start_price {
-                Err("即決価格が開始価格より低いです".into())
-            } else {
-                Ok(Some(price))
-            }
+    let start_price = unwrap_or_return!(discord_helper::await_right_reply(ctx, msg, |content| {
+        let price = read_price(content, &units)?;
+        if price == 0 {
+            Err("開始価格を0にすることはできません".into())
         } else {
-            Err("価格の形式が正しくありません\n**※次のように入力してください。【〇LC+△ST+□】 or　【〇ST+△】 or 【△】 ex.1lc+1st+1 or 1st+1 or 32**".into())
+            Ok(price)
         }
     }).await);
 
+    let (bin_price, reserve_price, min_increment, dutch) = match auction_type {
+        AuctionType::English | AuctionType::Sealed => {
+            // 封印入札・複数個の出品には即決価格を設けない
+            let bin_price = if auction_type == AuctionType::English && quantity == 1 {
+                msg.channel_id.send_message(ctx, |m| {
+                    m.embed(|e| {
+                        e.description(format!("即決価格を入力してください。\n{}\nない場合は`なし`とお書きください。", PRICE_FORMAT_HELP)).color(0xffaf60)
+                    })
+                }).await?;
+                unwrap_or_return!(discord_helper::await_right_reply(ctx, msg, |content| {
+                    if content == "なし" {
+                        Ok(None)
+                    } else {
+                        let price = read_price(content, &units)?;
+                        if price == start_price {
+                            Err("即決価格が開始価格と等しいです。(価格が決まっているのであれば取引チャンネルをお使いください。)".into())
+                        } else if price < start_price {
+                            Err("即決価格が開始価格より低いです".into())
+                        } else {
+                            Ok(Some(price))
+                        }
+                    }
+                }).await)
+            } else {
+                None
+            };
+
+            msg.channel_id.send_message(ctx, |m| {
+                m.embed(|e| {
+                    e.description(format!(
+                        "最低落札価格を入力してください。最高入札額がこの価格に達しなかった場合、オークションは不成立になります。\n
+                        最低落札価格は公開されず、達したかどうかのみが表示されます。\n
+                        {}\n
+                        ない場合は`なし`とお書きください。", PRICE_FORMAT_HELP)).color(0xffaf60)
+                })
+            }).await?;
+            let reserve_price = unwrap_or_return!(discord_helper::await_right_reply(ctx, msg, |content| {
+                if content == "なし" {
+                    Ok(None)
+                } else {
+                    let price = read_price(content, &units)?;
+                    if price <= start_price {
+                        Err("最低落札価格は開始価格より高くしてください".into())
+                    } else if bin_price.map_or(false, |bin_price| price > bin_price) {
+                        Err("最低落札価格が即決価格より高いです".into())
+                    } else {
+                        Ok(Some(price))
+                    }
+                }
+            }).await);
+
+            msg.channel_id.send_message(ctx, |m| {
+                m.embed(|e| {
+                    e.description(format!(
+                        "最低入札単位を入力してください。\n
+                        金額で指定する場合は価格を、現在価格に対する割合で指定する場合は`5%`のように入力してください。\n
+                        {}\n
+                        ない場合は`なし`とお書きください。", PRICE_FORMAT_HELP)).color(0xffaf60)
+                })
+            }).await?;
+            let min_increment = unwrap_or_return!(discord_helper::await_right_reply(ctx, msg, |content| {
+                if content == "なし" {
+                    Ok(None)
+                } else if let Some(percent) = content.trim().strip_suffix('%') {
+                    match percent.trim().parse::<i32>() {
+                        Ok(percent) if 1 <= percent && percent <= 100 => Ok(Some(MinIncrement::Percent(percent))),
+                        Ok(_) => Err("割合は1%~100%の範囲で指定してください".into()),
+                        Err(_) => Err("割合の形式が正しくありません(ex. 5%)".into()),
+                    }
+                } else {
+                    let price = read_price(content, &units)?;
+                    if price == 0 {
+                        Err("最低入札単位を0にすることはできません".into())
+                    } else {
+                        Ok(Some(MinIncrement::Amount(price)))
+                    }
+                }
+            }).await);
+            (bin_price, reserve_price, min_increment, None)
+        },
+        AuctionType::Dutch => {
+            msg.channel_id.send_message(ctx, |m| {
+                m.embed(|e| {
+                    e.description(format!(
+                        "最低価格を入力してください。価格はこれより下がりません。\n
+                        {}", PRICE_FORMAT_HELP)).color(0xffaf60)
+                })
+            }).await?;
+            let floor_price = unwrap_or_return!(discord_helper::await_right_reply(ctx, msg, |content| {
+                let price = read_price(content, &units)?;
+                if price == 0 {
+                    Err("最低価格を0にすることはできません".into())
+                } else if price >= start_price {
+                    Err("最低価格は開始価格より低くしてください".into())
+                } else {
+                    Ok(price)
+                }
+            }).await);
+
+            msg.channel_id.send_message(ctx, |m| {
+                m.embed(|e| {
+                    e.description(format!(
+                        "1回あたりの値下げ幅を入力してください。\n
+                        {}", PRICE_FORMAT_HELP)).color(0xffaf60)
+                })
+            }).await?;
+            let step = unwrap_or_return!(discord_helper::await_right_reply(ctx, msg, |content| {
+                let price = read_price(content, &units)?;
+                if price == 0 {
+                    Err("値下げ幅を0にすることはできません".into())
+                } else if price > start_price - floor_price {
+                    Err("値下げ幅が開始価格と最低価格の差より大きいです".into())
+                } else {
+                    Ok(price)
+                }
+            }).await);
+
+            msg.channel_id.send_message(ctx, |m| {
+                m.embed(|e| {
+                    e.description("値下げの間隔を入力してください。\n
+                    例 1時間30分ごとに値下げしたい場合:\n**1h30m**と入力してください。").color(0xffaf60)
+                })
+            }).await?;
+            let interval_minutes = unwrap_or_return!(discord_helper::await_right_reply(ctx, msg, |content| {
+                match formats::duration(content) {
+                    Some((0, duration)) if Duration::minutes(1) <= duration && duration <= Duration::weeks(1) => Ok(duration.num_minutes() as i32),
+                    Some(_) => Err("値下げの間隔は1分~1週間の範囲で指定してください".into()),
+                    None => Err("間隔の書式が正しくありません(ex. 1h30m)".into()),
+                }
+            }).await);
+
+            // auction_idとnext_drop_atは開始時に決める
+            let dutch = NewDutchSchedule {
+                auction_id: 0, floor_price, step, interval_minutes, current_price: start_price, next_drop_at: Utc::now(),
+            };
+            (None, None, None, Some(dutch))
+        },
+    };
+
+    let second_price = if auction_type == AuctionType::Sealed {
+        msg.channel_id.send_message(ctx, |m| {
+            m.embed(|e| {
+                e.description("落札者が支払う価格を選んでください。\n
+                `最高額`: 落札者が自分の入札額を支払う\n
+                `セカンドプライス`: 落札者が2番目に高い入札額+最低入札単位を支払う").color(0xffaf60)
+            })
+        }).await?;
+        unwrap_or_return!(discord_helper::await_right_reply(ctx, msg, |content| {
+            match content.trim() {
+                "最高額" => Ok(false),
+                "セカンドプライス" => Ok(true),
+                _ => Err("`最高額`か`セカンドプライス`で答えてください".into()),
+            }
+        }).await)
+    } else {
+        false
+    };
+
     msg.channel_id.send_message(ctx, |m| {
         m.embed(|e| {
             e.description(format!("オークション終了日時を入力してください。\n**注意！**時間の書式に注意してください！\n\n
             例 {0}年5月14日の午後8時に終了したい場合：\n**{0}/05/14-20:00**と入力してください。\n\n
             例 1カ月2週間3日4時間5分後に終了したい場合:\n**1M2w3d4h5m**と入力してください。\n\n
-            終了したい場合は**cancel**と入力してください", Local::now().naive_local().year())).color(0xffaf60)
+            `明日20時`、`土曜21時`、`3日後`のようにも入力できます。\n\n
+            終了したい場合は**cancel**と入力してください", clock::local_now().year())).color(0xffaf60)
         })
     }).await?;
-    let (end_time, end_time_txt) = unwrap_or_return!(discord_helper::await_right_reply(ctx, msg, |content| {
-        let now = Local::now().naive_local();
+    let (end_time, end_time_txt) = unwrap_or_return!(discord_helper::await_right_reply(ctx, msg, parse_end_time).await);
 
-        let time = if let Some((year, month, day, hour, minute)) = formats::datetime(content) {
-            if !(2000 <= year && year <= 3000) {
-                return Err("年は2000~3000の範囲で指定してください".into());
-            }
-            if !(1 <= month && month <= 12) {
-                return Err("存在しない月です".into());
-            }
-            if !(1 <= day && day <= formats::last_day(year, month)) {
-                return Err("存在しない日です".into());
-            }
-            let date = NaiveDate::from_ymd(year, month, day);
-            let datetime;
-            if (hour, minute) == (24, 00) {
-                datetime = date.and_hms(0, 0, 0) + Duration::days(1);
-            } else if !(hour < 24 && minute < 60) {
-                return Err("範囲外の時刻です".into());
+    let extension = if auction_type == AuctionType::English {
+        let channel_extension_text = channel_extension.map_or_else(|| "なし".into(), |extension| extension.describe());
+        msg.channel_id.send_message(ctx, |m| {
+            m.embed(|e| {
+                e.description(format!("終了間際に入札があった場合、終了日時を延長しますか？\n
+                `10 5`のように入力すると、終了10分前以降に入札があった場合に終了日時を入札の5分後まで延長します。\n
+                チャンネルの設定({})を使う場合は`デフォルト`、延長しない場合は`なし`とお書きください。", channel_extension_text)).color(0xffaf60)
+            })
+        }).await?;
+        unwrap_or_return!(discord_helper::await_right_reply(ctx, msg, |content| {
+            if content == "デフォルト" {
+                Ok(channel_extension)
+            } else if content == "なし" {
+                Ok(None)
+            } else if let Some((window, minutes)) = formats::end_extension(content) {
+                Ok(Some(EndExtension { window, minutes }))
             } else {
-                datetime = date.and_hms(hour, minute, 0);
+                Err("延長設定の形式が正しくありません\n**※`終了何分前以降の入札で延長するか 何分延長するか`の形式で入力してください。ex. 10 5**".into())
             }
-            (datetime, year, month, day, hour, minute)
-
-        } else if let Some(duration) = formats::duration(content) {
-            let time = now.clone();
-            let mut month = time.year()*12 + time.month() as i32 - 1;
-            month += duration.0;
-            let year = month / 12;
-            let month = (month%12+1) as u32;
-            let mut time = NaiveDate::from_ymd(year, month, time.day().min(formats::last_day(year, month))).and_time(time.time());
-            time += duration.1;
-            (time, time.year(), time.month(), time.day(), time.hour(), time.minute())
-
-        } else {
-            let year = now.year();
-            return Err(format!("時間の書式が正しくありません\n\n
-            例 {0}年5月14日の午後8時に終了したい場合：\n**{0}/05/14-20:00**と入力してください。\n\n
-            例 1カ月2週間3日4時間5分後に終了したい場合:\n**1M2w3d4h5m**と入力してください。\n\n", year));
-        };
-
-        let duration = time.0 - now;
-        if duration <= Duration::zero() {
-            Err("終了時刻を現在時刻以前にすることはできません。".into())
-        } else if duration <= Duration::hours(12) {
-            Err("開催期間を12時間以下にすることはできません。".into())
-        } else if duration >= Duration::weeks(8) {
-            Err("2ヶ月以上にわたるオークションはできません。".into())
-        } else {
-            Ok((time.0, format!("{:0>4}/{:0>2}/{:0>2} {:0>2}:{:0>2}", time.1, time.2, time.3, time.4, time.5)))
-        }
-    }).await);
+        }).await)
+    } else {
+        None
+    };
 
     msg.channel_id.send_message(ctx, |m| {
         m.embed(|e| {
@@ -185,16 +409,25 @@ async fn start(ctx: &Context, msg: &Message) -> CommandResult {
 
     
     let channel_id = msg.channel_id.0 as i64;
+    let (min_increment, min_increment_percent) = MinIncrement::into_columns(min_increment);
+    let (extension_window, extension_minutes) = EndExtension::into_columns(extension);
+    let dutch_text = dutch.as_ref().map(|dutch| dutch.describe(&unit, &units));
     let new_auction = NewAuctionInfo {
         channel_id, owner_id: msg.author.id.0 as i64, item, unit, start_price, bin_price, end_time, notice,
+        min_increment, min_increment_percent, extension_window, extension_minutes, reserve_price,
+        auction_type: auction_type.as_str().into(), second_price, quantity,
     };
-    let embed_editter = new_auction.info_embed(formats::display_name(&ctx, &msg.author, msg.guild(&ctx).await).await, end_time_txt.clone());
+    let embed_editter = new_auction.info_embed(formats::display_name(&ctx, &msg.author, msg.guild(&ctx).await).await, end_time_txt.clone(), None, &units);
 
     discord_helper::purge(&ctx, msg.channel_id, msg.id).await?;
     msg.channel_id.send_message(&ctx, |m| {
         m.embed(|e| {
             e.title("これで始めます。よろしいですか？YES/NOで答えてください。(小文字でもOK。NOの場合初めからやり直してください。)");
-            embed_editter(e)
+            embed_editter(e);
+            if let Some(dutch_text) = &dutch_text {
+                e.field("値下げ", dutch_text, false);
+            }
+            e
         })
     }).await?;
     if !unwrap_or_return!(discord_helper::await_right_reply(&ctx, msg, |content| {
@@ -206,87 +439,626 @@ async fn start(ctx: &Context, msg: &Message) -> CommandResult {
     }
 
     discord_helper::purge(&ctx, msg.channel_id, msg.id).await?;
-    let new_auction: AuctionInfo = diesel::insert_into(auction_info).values(&new_auction).get_result(&conn)?;
+
+    // 開催中のオークションか順番待ちがあれば、開催期間を記録して順番待ちとして登録する。なければ開催中として登録する
+    // 終了処理の順番待ちの開始と同時にならないよう、チャンネルの行をロックして判定し、登録まで1つのトランザクションで行う
+    let (new_auction, queued) = conn.transaction::<_, diesel::result::Error, _>(|| {
+        let current_auction = channel_auction.find(channel_id).select(auction_col).for_update().get_result::<Option<i32>>(&conn)?;
+        let queue_length = AuctionManager::queue_length(&conn, msg.channel_id)?;
+        let queued = current_auction.is_some() || queue_length > 0;
+        let (status, duration_minutes) = if queued {
+            (AuctionStatus::Queued, Some((end_time - Utc::now()).num_minutes() as i32))
+        } else {
+            (AuctionStatus::Active, None)
+        };
+        let new_auction: AuctionInfo = diesel::insert_into(auction_info)
+            .values((&new_auction, status_col.eq(status.as_str()), duration_minutes_col.eq(duration_minutes)))
+            .get_result(&conn)?;
+        if let Some(mut dutch) = dutch {
+            dutch.auction_id = new_auction.id;
+            dutch.next_drop_at = Utc::now() + Duration::minutes(dutch.interval_minutes as i64);
+            diesel::insert_into(dutch_schedule).values(&dutch).execute(&conn)?;
+        }
+        if queued {
+            Ok((new_auction, Some((queue_length, current_auction.is_none()))))
+        } else {
+            diesel::update(channel_auction.find(channel_id)).set(auction_col.eq(new_auction.id)).execute(&conn)?;
+            Ok((new_auction, None))
+        }
+    })?;
+    if let Some((queue_length, channel_free)) = queued {
+        msg.channel_id.say(&ctx, format!("順番待ちに追加しました (id:{}, {}番目)\n`!queue`で順番を確認できます", new_auction.id, queue_length + 1)).await?;
+        if channel_free {
+            AuctionManager::start_queued(&ctx, msg.channel_id).await?;
+        }
+        return Ok(());
+    }
+
     let embed_message = msg.channel_id.send_message(&ctx, |m| {
         m.content("オークションを開始します")
          .embed(|e| {
             e.title("オークション内容").field("ID", new_auction.id, false);
-            embed_editter(e)
+            embed_editter(e);
+            if let Some(dutch_text) = &dutch_text {
+                e.field("値下げ", dutch_text, false);
+            }
+            e
         })
     }).await?;
     embed_message.pin(&ctx).await?;
     diesel::update(auction_info).filter(auction_id_col.eq(new_auction.id)).set(embed_id.eq(Some(embed_message.id.0 as i64))).execute(&conn)?;
-    diesel::update(channel_auction.find(channel_id)).set(auction_col.eq(new_auction.id)).execute(&conn)?;
     
     
     Ok(())
 }
 
 
+// チャンネルの順番待ちの一覧と、自分の出品の順番
 #[command]
-async fn tend(ctx: &Context, msg: &Message, mut args: Args) -> CommandResult{
+async fn queue(ctx: &Context, msg: &Message) -> CommandResult {
     let conn = ctx.get_connection().await;
 
-    let manager = AuctionManager::from_channel(&conn, msg.channel_id)?;
+    let queued = auction_info.filter(info_channel_col.eq(msg.channel_id.0 as i64)).filter(status_col.eq(AuctionStatus::Queued.as_str()))
+        .order(auction_id_col.asc()).load::<AuctionInfo>(&conn)?;
+    if queued.is_empty() {
+        msg.channel_id.say(&ctx.http, "このチャンネルに順番待ちのオークションはありません").await?;
+        return Ok(());
+    }
+
+    let mut description = queued.iter().enumerate().map(|(i, info)| {
+        format!("{}. {} (出品者: <@{}>, id:{})", i + 1, info.item, info.owner_id, info.id)
+    }).collect::<Vec<_>>().join("\n");
+    let own_places = queued.iter().enumerate()
+        .filter(|(_, info)| info.owner_id == msg.author.id.0 as i64)
+        .map(|(i, _)| format!("{}番目", i + 1))
+        .collect::<Vec<_>>();
+    if !own_places.is_empty() {
+        description.push_str(&format!("\n\nあなたの出品: {}", own_places.join(", ")));
+    }
+    msg.channel_id.send_message(&ctx.http, |m| {
+        m.embed(|e| {
+            e.title("オークションの順番待ち").description(description).color(0xffaf60)
+        })
+    }).await?;
+    Ok(())
+}
+
+async fn say_get_auction_error(ctx: &Context, msg: &Message, error: GetAuctionError) -> CommandResult {
+    match error {
+        GetAuctionError::NotAuctionChannel => {
+            msg.channel_id.say(&ctx.http, NOT_AUCTION_CHANNEL).await?;
+        },
+        GetAuctionError::NotHeld => {
+            msg.channel_id.say(&ctx.http, format!("オークションが開催されていません")).await?;
+        },
+        _ => unreachable!(),
+    }
+    Ok(())
+}
+
+async fn announce_tends(ctx: &Context, channel_id: ChannelId, unit: &str, units: &UnitSystem, tends: &[TendInfo]) -> CommandResult {
+    for tend in tends {
+        let header = if tend.message_id.is_none() { "自動入札\n" } else { "" };
+        let quantity = if tend.quantity > 1 { format!(" × {}個", tend.quantity) } else { String::new() };
+        channel_id.send_message(&ctx.http, |m| {
+            m.embed(|e| {
+                e.description(format!("{}入札者: **{}**,\n入札額: **{}{}**{}", header, tend.tender_name, unit, units.with_raw(tend.price), quantity))
+                 .footer(|f| f.text(format!("入札ID: {}", tend.id)))
+                 .color(0x4259fb)
+            })
+        }).await?;
+    }
+    Ok(())
+}
+
+// 記録された入札を告知し、即決による終了や終了日時の延長があればそれも処理する。ピン留めのembedも更新する
+async fn report_outcome(ctx: &Context, manager: &mut AuctionManager, outcome: TendOutcome) -> CommandResult {
+    let channel_id = ChannelId(manager.channel_id);
+    let TendOutcome { finished, extended, mut tends } = outcome;
 
-    let mut manager = match manager {
+    let last_tend = if finished { tends.pop() } else { None };
+    announce_tends(ctx, channel_id, &manager.unit, &manager.units, &tends).await?;
+
+    if let Some(last_tend) = last_tend {
+        // 終了処理を先に記録する。定期処理が先に終了処理していれば、結果はそちらで告知される
+        let conn = ctx.get_connection().await;
+        if let Some((status, settlements)) = manager.finish(&conn)? {
+            let _ = channel_id.send_message(
+                ctx, |m| {
+                    m.embed(|e| {
+                        e.description(format!("即決価格以上の入札がされました\n落札者: **{}**\n落札額: **{}{}**", last_tend.tender_name, manager.unit, manager.units.with_raw(last_tend.price)))
+                         .color(0x4259fb)
+                    })
+                }
+            ).await;
+            let _ = channel_id.say(&ctx, "--------ｷﾘﾄﾘ線--------").await;
+            manager.after_finish(&ctx, status, &settlements).await?;
+        }
+    } else {
+        if extended {
+            channel_id.send_message(&ctx.http, |m| {
+                m.embed(|e| {
+                    e.description(format!("終了間際の入札があったため、終了日時を**{}**まで延長しました", formats::datetime_text(&manager.end_time)))
+                     .color(0xffaf60)
+                })
+            }).await?;
+        }
+        manager.update_embed(&ctx).await?;
+    }
+    Ok(())
+}
+
+fn tend_error_text(manager: &AuctionManager, error: TendError) -> String {
+    match error {
+        TendError::LessThanStartPrice => format!("入札価格が開始価格({})より低いです", manager.price_text(manager.start_price)),
+        TendError::LastTendOrLess(price) => if manager.quantity > 1 {
+            format!("入札価格があなたの前回の入札価格({})以下です", manager.price_text(price))
+        } else {
+            format!("入札価格が現在の入札価格({})以下です", manager.price_text(price))
+        },
+        TendError::SameTender => "同一人物による入札は出来ません。".into(),
+        TendError::ByOwner => "出品者が入札は出来ません。".into(),
+        TendError::Finished => "このオークションは既に終了しています。".into(),
+        TendError::BelowMinIncrement => format!("入札価格が最低入札価格({})より低いです (最低入札単位: {})",
+            manager.units.with_raw(manager.min_next_price()), manager.min_increment.unwrap().describe(&manager.unit, &manager.units)),
+        TendError::DutchAuction => "このオークションはダッチオークションです。`!buy`で現在価格で購入してください。".into(),
+        TendError::NotDutch => "このオークションはダッチオークションではありません。`!tend`で入札してください。".into(),
+        TendError::Sealed => format!("このオークションは封印入札です。入札額が他の人に見えないよう、Botに`!tend {} 価格`とDMで送ってください。", manager.id),
+        TendError::NotSealed => "DMで入札できるのは封印入札のみです。オークションチャンネルで`!tend`してください。".into(),
+        TendError::InvalidQuantity => if manager.quantity > 1 {
+            format!("個数は1~{}個の範囲で`!tend 価格 x個数`の形式で指定してください", manager.quantity)
+        } else {
+            "このオークションでは個数を指定できません".into()
+        },
+        TendError::BelowWinningPrice => format!("入札価格が落札圏内に入る最低価格({})より低いです", manager.units.with_raw(manager.min_next_price())),
+        TendError::ProxyUnavailable => "複数個の出品では自動入札は使えません。".into(),
+    }
+}
+
+// 封印入札へのDMでの入札。`!tend オークションID 価格`の形式
+async fn sealed_tend_by_dm(ctx: &Context, msg: &Message, args: Args) -> CommandResult {
+    let conn = ctx.get_connection().await;
+
+    let mut content = args.message().trim().splitn(2, char::is_whitespace);
+    let auction_id = content.next().and_then(|id| id.parse::<i32>().ok());
+    let price_text = content.next().map(str::trim);
+    let (auction_id, price_text) = match (auction_id, price_text) {
+        (Some(auction_id), Some(price_text)) => (auction_id, price_text),
+        _ => {
+            msg.channel_id.say(&ctx.http, format!("`!tend オークションID 価格` の形式で入力してください\n{}", PRICE_FORMAT_HELP)).await?;
+            return Ok(());
+        }
+    };
+    let mut manager = match AuctionManager::from_id(&conn, auction_id)? {
         Ok(manager) => manager,
+        Err(_) => {
+            msg.channel_id.say(&ctx.http, "指定されたオークションが見つかりません").await?;
+            return Ok(());
+        }
+    };
+    // 単位はオークションのサーバーの定義で読み取る
+    let price = match read_price(price_text, &manager.units) {
+        Ok(price) => price,
         Err(error) => {
-            match error {
-                GetAuctionError::NotAuctionChannel => {
-                    msg.channel_id.say(&ctx.http, "このチャンネルはオークションチャンネルではありません").await?;
-                },
-                GetAuctionError::NotHeld => {
-                    msg.channel_id.say(&ctx.http, format!("オークションが開催されていません")).await?;
-                },
-                _ => unreachable!(),
-            }
+            msg.channel_id.say(&ctx.http, error).await?;
             return Ok(());
         }
     };
 
-    let price: i32 = args.single()?;
+    let tender_name = formats::display_name(&ctx, &msg.author, discord_helper::guild_of(ctx, ChannelId(manager.channel_id)).await).await;
+    match manager.sealed_tend(&conn, msg.author.id.0, &tender_name, msg.id.0, price)? {
+        Ok(tend) => {
+            msg.channel_id.say(&ctx.http, format!("オークション(id:{})に**{}{}**で入札しました。結果は終了日時({})に公開されます。\n入札し直すと以前の入札は置き換えられます。",
+                manager.id, manager.unit, manager.units.with_raw(tend.price), formats::datetime_text(&manager.end_time))).await?;
+            manager.update_embed(&ctx).await?;
+        },
+        Err(error) => {
+            msg.channel_id.say(&ctx.http, tend_error_text(&manager, error)).await?;
+        }
+    }
+    Ok(())
+}
+
+// `価格 x個数`(または`価格 ×個数`)の形式。個数がなければNone
+// `1lc + 5個`のような価格の項と区別するため、個数にはxか×を必ず付ける
+fn split_quantity(text: &str) -> (&str, Option<i32>) {
+    if let Some((price_text, quantity_text)) = text.trim().rsplit_once(char::is_whitespace) {
+        let number = quantity_text.strip_prefix(|c: char| c == 'x' || c == '×');
+        if let Some(Ok(quantity)) = number.map(str::parse::<i32>) {
+            return (price_text.trim(), Some(quantity));
+        }
+    }
+    (text.trim(), None)
+}
+
+#[command]
+async fn tend(ctx: &Context, msg: &Message, args: Args) -> CommandResult{
+    if msg.guild_id.is_none() {
+        return sealed_tend_by_dm(ctx, msg, args).await;
+    }
+
+    let conn = ctx.get_connection().await;
+
+    let mut manager = match AuctionManager::from_channel(&conn, msg.channel_id)? {
+        Ok(manager) => manager,
+        Err(error) => return say_get_auction_error(ctx, msg, error).await,
+    };
+    // 封印入札では入札額が見えないよう、価格を読み取る前にメッセージを消しておく(書式の誤りでも消す)
+    if manager.auction_type == AuctionType::Sealed {
+        let _ = msg.delete(&ctx).await;
+    }
+
+    // `!tend max 価格`は自動入札の上限額の登録
+    let content = args.rest().trim();
+    // `maxfoo`のような入力は自動入札として扱わない
+    let (is_proxy, price_text) = match content.strip_prefix("max") {
+        Some(price_text) if price_text.is_empty() || price_text.starts_with(char::is_whitespace) => (true, price_text.trim()),
+        _ => (false, content),
+    };
+    let (price_text, quantity) = split_quantity(price_text);
+    let price = match read_price(price_text, &manager.units) {
+        Ok(price) => price,
+        Err(error) => {
+            msg.channel_id.say(&ctx.http, error).await?;
+            return Ok(());
+        }
+    };
     let tender_name = formats::display_name(&ctx, &msg.author, msg.guild(&ctx).await).await;
-    let format_price = format!("{}{}", manager.unit, formats::stack_with_raw(price));
 
-    let tend_result = manager.tend(&conn, msg.author.id.0, price);
+    let tend_result = if is_proxy {
+        // 上限額が他の入札者に見えないよう、コマンドのメッセージは削除しておく
+        let _ = msg.delete(&ctx).await;
+        manager.set_proxy(&conn, msg.author.id.0, &tender_name, price)?
+    } else {
+        manager.tend(&conn, msg.author.id.0, &tender_name, msg.id.0, price, quantity.unwrap_or(1))?
+    };
     match tend_result {
-        Ok(finished) => {
-            if finished {
-                msg.channel_id.send_message(
-                    ctx, |m| {
-                        m.embed(|e| {
-                            e.description(format!("即決価格以上の入札がされました\n落札者: **{}**\n落札額: **{}**", tender_name, format_price))
-                             .color(0x4259fb)
-                        })
-                    }
-                ).await?;
-                msg.channel_id.say(&ctx, "--------ｷﾘﾄﾘ線--------").await?;
-                manager.finish(&ctx).await;
-            } else {
-                msg.channel_id.send_message(&ctx.http, |m| {
+        Ok(outcome) => {
+            if is_proxy {
+                msg.author.direct_message(&ctx, |m| {
+                    m.content(format!("オークション(id:{})の自動入札の上限額を**{}{}**に設定しました",
+                        manager.id, manager.unit, manager.units.with_raw(price)))
+                }).await?;
+                msg.channel_id.say(&ctx.http, format!("**{}**が自動入札を設定しました", tender_name)).await?;
+            }
+            report_outcome(ctx, &mut manager, outcome).await?;
+        },
+        Err(error) => {
+            msg.channel_id.say(&ctx.http, tend_error_text(&manager, error)).await?;
+        }
+    }
+    
+    Ok(())
+}
+
+// ダッチオークションで現在価格で購入する。取引チャンネルでは出品の購入になる
+#[command]
+async fn buy(ctx: &Context, msg: &Message, args: Args) -> CommandResult {
+    let conn = ctx.get_connection().await;
+
+    if deal_manager::is_deal_channel(&conn, msg.channel_id)? {
+        return super::deal::buy_deal(ctx, msg, args).await;
+    }
+
+    let mut manager = match AuctionManager::from_channel(&conn, msg.channel_id)? {
+        Ok(manager) => manager,
+        Err(error) => return say_get_auction_error(ctx, msg, error).await,
+    };
+
+    let buyer_name = formats::display_name(&ctx, &msg.author, msg.guild(&ctx).await).await;
+    match manager.buy(&conn, msg.author.id.0, &buyer_name, msg.id.0)? {
+        Ok(tend) => {
+            // 終了処理を先に記録する。定期処理が先に終了処理していれば、結果はそちらで告知される
+            if let Some((status, settlements)) = manager.finish(&conn)? {
+                let _ = msg.channel_id.send_message(&ctx.http, |m| {
                     m.embed(|e| {
-                        e.description(format!("入札者: **{}**,\n入札額: **{}**", tender_name, format_price))
+                        e.description(format!("購入されました\n落札者: **{}**\n落札額: **{}{}**", tend.tender_name, manager.unit, manager.units.with_raw(tend.price)))
+                         .footer(|f| f.text(format!("入札ID: {}", tend.id)))
                          .color(0x4259fb)
                     })
-                }).await?;
+                }).await;
+                let _ = msg.channel_id.say(&ctx, "--------ｷﾘﾄﾘ線--------").await;
+                manager.after_finish(&ctx, status, &settlements).await?;
+            }
+        },
+        Err(error) => {
+            msg.channel_id.say(&ctx.http, tend_error_text(&manager, error)).await?;
+        }
+    }
+    Ok(())
+}
+
+// オークションの中止。出品者は入札がない間のみ、モデレーターはいつでも中止できる
+#[command]
+async fn cancel_auction(ctx: &Context, msg: &Message, args: Args) -> CommandResult {
+    let conn = ctx.get_connection().await;
+
+    let mut manager = match AuctionManager::from_channel(&conn, msg.channel_id)? {
+        Ok(manager) => manager,
+        Err(error) => return say_get_auction_error(ctx, msg, error).await,
+    };
+
+    let reason = match args.message().trim() {
+        "" => "なし",
+        reason => reason,
+    };
+    let is_moderator = discord_helper::is_moderator(ctx, msg).await;
+    match manager.cancel(&conn, msg.author.id.0, is_moderator)? {
+        Ok(()) => {
+            // 告知に失敗してもチャンネルを空けられるよう、終了処理を先に記録する
+            if let Some((status, settlements)) = manager.finish(&conn)? {
+                let _ = msg.channel_id.send_message(&ctx.http, |m| {
+                    m.embed(|e| {
+                        e.description(format!("{}は中止されました\n理由: {}", manager.item, reason))
+                         .footer(|f| f.text(format!("ID: {}", manager.id)))
+                         .color(0xffaf60)
+                    })
+                }).await;
+                let _ = msg.channel_id.say(&ctx, "--------ｷﾘﾄﾘ線--------").await;
+                manager.after_finish(&ctx, status, &settlements).await?;
             }
         },
         Err(error) => {
             let content = match error {
-                TendError::LessThanStartPrice => format!("入札価格が開始価格({})より低いです", manager.start_price),
-                TendError::LastTendOrLess => format!("入札価格が現在の入札価格({})以下です", manager.tend.last().unwrap().price),
-                TendError::SameTender => "同一人物による入札は出来ません。".into(),
-                TendError::ByOwner => "出品者が入札は出来ません。".into(),
+                CancelError::NotOwner => "オークションを中止できるのは出品者かモデレーターのみです",
+                CancelError::AlreadyTended => "入札があった後は出品者が中止することはできません。モデレーターに依頼してください",
+                CancelError::Finished => "このオークションは既に終了しています。",
             };
             msg.channel_id.say(&ctx.http, content).await?;
         }
     }
-    
+    Ok(())
+}
+
+const EDIT_HELP: &str = "`!edit 項目 内容` の形式で入力してください。\n\
+    項目: `出品物`, `特記事項`, `即決価格`(`なし`で削除), `終了日時`\n\
+    入札があった後は特記事項のみ変更できます。";
+
+// 出品者による出品内容の変更
+#[command]
+async fn edit(ctx: &Context, msg: &Message, args: Args) -> CommandResult {
+    let conn = ctx.get_connection().await;
+
+    let mut manager = match AuctionManager::from_channel(&conn, msg.channel_id)? {
+        Ok(manager) => manager,
+        Err(error) => return say_get_auction_error(ctx, msg, error).await,
+    };
+
+    let mut content = args.message().trim().splitn(2, char::is_whitespace);
+    let field = content.next().unwrap_or("");
+    let value = content.next().map(str::trim).unwrap_or("");
+    if value.is_empty() {
+        msg.channel_id.say(&ctx.http, EDIT_HELP).await?;
+        return Ok(());
+    }
+    let edit = match field {
+        "出品物" | "item" => {
+            if value.contains("\n") {
+                msg.channel_id.say(&ctx.http, "出品物に改行を含めてはいけません").await?;
+                return Ok(());
+            }
+            AuctionEdit::Item(value.to_string())
+        },
+        "特記事項" | "notice" => AuctionEdit::Notice(value.to_string()),
+        "即決価格" | "bin" => {
+            if value == "なし" {
+                AuctionEdit::BinPrice(None)
+            } else {
+                match read_price(value, &manager.units) {
+                    Ok(price) => AuctionEdit::BinPrice(Some(price)),
+                    Err(error) => {
+                        msg.channel_id.say(&ctx.http, error).await?;
+                        return Ok(());
+                    }
+                }
+            }
+        },
+        "終了日時" | "end" => match parse_end_time(value) {
+            Ok((end_time, _)) => AuctionEdit::EndTime(end_time),
+            Err(error) => {
+                msg.channel_id.say(&ctx.http, error).await?;
+                return Ok(());
+            }
+        },
+        _ => {
+            msg.channel_id.say(&ctx.http, EDIT_HELP).await?;
+            return Ok(());
+        }
+    };
+
+    match manager.edit(&conn, msg.author.id.0, edit)? {
+        Ok((field, old_value, new_value)) => {
+            msg.channel_id.send_message(&ctx.http, |m| {
+                m.embed(|e| {
+                    e.description(format!("出品者が{}を変更しました\n変更前: {}\n変更後: {}", field, old_value, new_value))
+                     .color(0xffaf60)
+                })
+            }).await?;
+            manager.update_embed(&ctx).await?;
+        },
+        Err(error) => {
+            let content = match error {
+                EditError::NotOwner => "出品者以外は変更できません".to_string(),
+                EditError::Finished => "このオークションは既に終了しています。".into(),
+                EditError::AlreadyTended => "入札があった後は特記事項のみ変更できます".into(),
+                EditError::BinPriceUnavailable => "この形式のオークションには即決価格を設けられません".into(),
+                EditError::BinPriceTooLow => "即決価格は開始価格より高く、最低落札価格以上にしてください".into(),
+            };
+            msg.channel_id.say(&ctx.http, content).await?;
+        }
+    }
+    Ok(())
+}
+
+async fn report_removal(ctx: &Context, manager: &mut AuctionManager, removed: TendInfo, reason: &str, outcome: TendOutcome) -> CommandResult {
+    // 封印入札の入札額は終了まで公開しない
+    let sealed = manager.auction_type == AuctionType::Sealed;
+    let current = if sealed {
+        "入札額は終了時に公開されます".into()
+    } else if let Some(last_tend) = manager.tend.last() {
+        format!("現在の最高入札: **{}** **{}{}**", last_tend.tender_name, manager.unit, manager.units.with_raw(last_tend.price))
+    } else {
+        "現在、入札はありません".into()
+    };
+    let removed_price = if sealed { "非公開".into() } else { format!("{}{}", manager.unit, manager.units.with_raw(removed.price)) };
+    ChannelId(manager.channel_id).send_message(&ctx.http, |m| {
+        m.embed(|e| {
+            e.description(format!("**{}**の入札(**{}**)を取り消しました\n理由: {}\n{}",
+                removed.tender_name, removed_price, reason, current))
+             .footer(|f| f.text(format!("入札ID: {}", removed.id)))
+             .color(0xffaf60)
+        })
+    }).await?;
+    report_outcome(ctx, manager, outcome).await
+}
+
+fn remove_tend_error_text(error: RemoveTendError) -> String {
+    match error {
+        RemoveTendError::NotFound => "指定された入札が見つかりません".into(),
+        RemoveTendError::NotLastTend => "取り消せるのは現在最高額の自分の入札のみです".into(),
+        RemoveTendError::GracePeriodOver => format!("入札から{}分以上経過しているため取り消せません", RETRACT_GRACE_MINUTES),
+        RemoveTendError::Finished => "このオークションは既に終了しています。".into(),
+    }
+}
+
+#[command]
+async fn retract(ctx: &Context, msg: &Message) -> CommandResult {
+    let conn = ctx.get_connection().await;
+
+    let mut manager = match AuctionManager::from_channel(&conn, msg.channel_id)? {
+        Ok(manager) => manager,
+        Err(error) => return say_get_auction_error(ctx, msg, error).await,
+    };
+
+    let reason = "入札者による取り消し";
+    match manager.retract(&conn, msg.author.id.0)? {
+        Ok((removed, outcome)) => report_removal(ctx, &mut manager, removed, reason, outcome).await?,
+        Err(error) => {
+            msg.channel_id.say(&ctx.http, remove_tend_error_text(error)).await?;
+        }
+    }
+    Ok(())
+}
+
+#[command]
+#[required_permissions(MANAGE_MESSAGES)]
+async fn remove_tend(ctx: &Context, msg: &Message, args: Args) -> CommandResult {
+    let conn = ctx.get_connection().await;
+
+    let mut manager = match AuctionManager::from_channel(&conn, msg.channel_id)? {
+        Ok(manager) => manager,
+        Err(error) => return say_get_auction_error(ctx, msg, error).await,
+    };
+
+    let mut content = args.message().trim().splitn(2, char::is_whitespace);
+    let tend_id = match content.next().and_then(|id| id.parse::<i32>().ok()) {
+        Some(tend_id) => tend_id,
+        None => {
+            msg.channel_id.say(&ctx.http, "`!remove_tend 入札ID 理由` の形式で入力してください").await?;
+            return Ok(());
+        }
+    };
+    let reason = match content.next().map(str::trim) {
+        Some(reason) if !reason.is_empty() => format!("{} (モデレーターによる削除)", reason),
+        _ => "モデレーターによる削除".into(),
+    };
+
+    match manager.remove_tend(&conn, tend_id, msg.author.id.0, &reason)? {
+        Ok((removed, outcome)) => report_removal(ctx, &mut manager, removed, &reason, outcome).await?,
+        Err(error) => {
+            msg.channel_id.say(&ctx.http, remove_tend_error_text(error)).await?;
+        }
+    }
+    Ok(())
+}
+
+
+// オークションチャンネルの登録・解除・一覧。チャンネルの管理権限が必要
+#[command]
+#[only_in(guilds)]
+#[required_permissions(MANAGE_CHANNELS)]
+#[sub_commands(auction_channel_add, auction_channel_remove, auction_channel_list)]
+async fn auction_channel(ctx: &Context, msg: &Message) -> CommandResult {
+    msg.channel_id.say(&ctx.http, "`!auction_channel add` このチャンネルをオークションチャンネルに登録する\n\
+        `!auction_channel remove` このチャンネルの登録を解除する\n\
+        `!auction_channel list` このサーバーのオークションチャンネルの一覧").await?;
+    Ok(())
+}
+
+#[command("add")]
+#[only_in(guilds)]
+#[required_permissions(MANAGE_CHANNELS)]
+async fn auction_channel_add(ctx: &Context, msg: &Message) -> CommandResult {
+    let conn = ctx.get_connection().await;
+
+    if deal_manager::is_deal_channel(&conn, msg.channel_id)? {
+        msg.channel_id.say(&ctx.http, "取引チャンネルはオークションチャンネルにできません").await?;
+        return Ok(());
+    }
+    let result = diesel::insert_into(channel_auction)
+        .values((channel_col.eq(msg.channel_id.0 as i64), guild_id_col.eq(msg.guild_id.map(|guild_id| guild_id.0 as i64))))
+        .on_conflict_do_nothing().execute(&conn)?;
+    if result == 0 {
+        msg.channel_id.say(&ctx.http, "このチャンネルは既にオークションチャンネルです").await?;
+    } else {
+        msg.channel_id.say(&ctx.http, "このチャンネルをオークションチャンネルに登録しました。`!start`で出品できます").await?;
+    }
+    Ok(())
+}
+
+#[command("remove")]
+#[only_in(guilds)]
+#[required_permissions(MANAGE_CHANNELS)]
+async fn auction_channel_remove(ctx: &Context, msg: &Message) -> CommandResult {
+    let conn = ctx.get_connection().await;
+
+    // 同時に!startされてもオークションのあるチャンネルを削除しないよう、チャンネルの行をロックして判定する
+    let content = conn.transaction::<_, diesel::result::Error, _>(|| {
+        let current_auction = match channel_auction.find(msg.channel_id.0 as i64).select(auction_col).for_update().get_result::<Option<i32>>(&conn).optional()? {
+            Some(current_auction) => current_auction,
+            None => return Ok("このチャンネルはオークションチャンネルではありません".to_string()),
+        };
+        if let Some(auction_id) = current_auction {
+            return Ok(format!("オークションが開催中のため解除できません (id:{})", auction_id));
+        }
+        let queue_length = AuctionManager::queue_length(&conn, msg.channel_id)?;
+        if queue_length > 0 {
+            return Ok(format!("順番待ちのオークションが{}件あるため解除できません", queue_length));
+        }
+        diesel::delete(channel_auction.find(msg.channel_id.0 as i64)).execute(&conn)?;
+        Ok("このチャンネルのオークションチャンネルの登録を解除しました".to_string())
+    })?;
+    msg.channel_id.say(&ctx.http, content).await?;
+    Ok(())
+}
+
+#[command("list")]
+#[only_in(guilds)]
+#[required_permissions(MANAGE_CHANNELS)]
+async fn auction_channel_list(ctx: &Context, msg: &Message) -> CommandResult {
+    let conn = ctx.get_connection().await;
+
+    let registered = channel_auction.filter(guild_id_col.eq(msg.guild_id.unwrap().0 as i64))
+        .order(channel_col.asc()).load::<ChannelAuction>(&conn)?;
+    if registered.is_empty() {
+        msg.channel_id.say(&ctx.http, "このサーバーにオークションチャンネルはありません").await?;
+        return Ok(());
+    }
+    let description = registered.iter().map(|row| {
+        match row.auction {
+            Some(auction_id) => format!("<#{}> 開催中 (id:{})", row.channel, auction_id),
+            None => format!("<#{}> 空き", row.channel),
+        }
+    }).collect::<Vec<_>>().join("\n");
+    msg.channel_id.send_message(&ctx.http, |m| {
+        m.embed(|e| {
+            e.title("オークションチャンネル").description(description).color(0xffaf60)
+        })
+    }).await?;
     Ok(())
 }
 
 
 #[group]
-#[commands(start, tend)]
-struct AuctionDeal;
\ No newline at end of file
+#[commands(start, queue, edit, cancel_auction, tend, buy, retract, remove_tend, auction_channel)]
+struct AuctionDeal;
@@ -0,0 +1,316 @@
+use serenity::prelude::*;
+use serenity::model::prelude::*;
+use serenity::framework::standard::{
+    macros::{group, command},
+    Args,
+    CommandResult,
+};
+use crate::schema::{
+    deal_info::dsl::{deal_info, id as deal_id_col, embed_id},
+    channel_deal::dsl::{channel_deal, channel as deal_channel_col, guild_id as deal_guild_col},
+    channel_auction::dsl::channel_auction,
+};
+use crate::utils::*;
+use crate::models::*;
+use super::auction_deal::{PRICE_FORMAT_HELP, read_time, read_price};
+use diesel;
+use diesel::prelude::*;
+use chrono::{Datelike, Duration, Utc};
+
+const NOT_DEAL_CHANNEL: &str = "このチャンネルは取引チャンネルではありません。\n\
+    チャンネルの管理権限を持つ人が`!deal_channel add`で取引チャンネルに登録できます";
+
+// 出品を掲載できる最長の期間
+const MAX_DEAL_WEEKS: i64 = 12;
+
+macro_rules! unwrap_or_return {
+    ($result:expr) => {
+        if let Some(content) = $result {
+            content
+        } else {
+            return Ok(());
+        }
+    }
+}
+
+// 掲載期限の入力を読み取る。オークションと違い、短い掲載期間も認める
+fn parse_deal_end_time(content: &str) -> Result<(chrono::DateTime<Utc>, String), String> {
+    let (end_time, end_time_txt) = read_time(content)?;
+    let duration = end_time - Utc::now();
+    if duration <= Duration::zero() {
+        Err("掲載期限を現在時刻以前にすることはできません。".into())
+    } else if duration > Duration::weeks(MAX_DEAL_WEEKS) {
+        Err(format!("掲載期間は{}週間以内にしてください。", MAX_DEAL_WEEKS))
+    } else {
+        Ok((end_time, end_time_txt))
+    }
+}
+
+// 取引チャンネルに固定価格で出品する
+#[command]
+async fn sell(ctx: &Context, msg: &Message) -> CommandResult {
+    let conn = ctx.get_connection().await;
+
+    if !deal_manager::is_deal_channel(&conn, msg.channel_id)? {
+        msg.channel_id.say(&ctx.http, NOT_DEAL_CHANNEL).await?;
+        return Ok(());
+    }
+    // 一覧に出せるよう、登録コマンド以前に登録されたチャンネルにはサーバーを記録しておく
+    diesel::update(channel_deal.find(msg.channel_id.0 as i64)).filter(deal_guild_col.is_null())
+        .set(deal_guild_col.eq(msg.guild_id.map(|guild_id| guild_id.0 as i64)))
+        .execute(&conn)?;
+
+    msg.channel_id.send_message(ctx, |m| {
+        m.embed(|e| {
+            e.description("何による取引ですか？単位を入力してください。(ex.GTギフト券, がちゃりんご, エメラルド etc)").color(0x4259fb)
+        })
+    }).await?;
+    let unit = unwrap_or_return!(discord_helper::await_right_reply(ctx, msg, |content| {
+        if content.contains("\n") {
+            Err("単位に改行を含めてはいけません".into())
+        } else {
+            Ok(content.to_string())
+        }
+    }).await);
+    let units = unit_manager::for_guild(&conn, msg.guild_id, &unit)?;
+
+    msg.channel_id.send_message(ctx, |m| {
+        m.embed(|e| {
+            e.description("出品するものを入力してください。").color(0x4259fb)
+        })
+    }).await?;
+    let item = unwrap_or_return!(discord_helper::await_right_reply(ctx, msg, |content| {
+        if content.contains("\n") {
+            Err("出品物に改行を含めてはいけません".into())
+        } else {
+            Ok(content.to_string())
+        }
+    }).await);
+
+    msg.channel_id.send_message(ctx, |m| {
+        m.embed(|e| {
+            e.description(format!("価格を入力してください。\n{}", PRICE_FORMAT_HELP)).color(0x4259fb)
+        })
+    }).await?;
+    let price = unwrap_or_return!(discord_helper::await_right_reply(ctx, msg, |content| {
+        let price = read_price(content, &units)?;
+        if price == 0 {
+            Err("価格を0にすることはできません".into())
+        } else {
+            Ok(price)
+        }
+    }).await);
+
+    msg.channel_id.send_message(ctx, |m| {
+        m.embed(|e| {
+            e.description(format!("掲載期限を入力してください。\n**注意！**時間の書式に注意してください！\n\n
+            例 {0}年5月14日の午後8時まで掲載したい場合：\n**{0}/05/14-20:00**と入力してください。\n\n
+            例 1週間掲載したい場合:\n**1w**と入力してください。(最長{1}週間)\n\n
+            `明日20時`、`土曜21時`、`3日後`のようにも入力できます。\n\n
+            終了したい場合は**cancel**と入力してください", clock::local_now().year(), MAX_DEAL_WEEKS)).color(0x4259fb)
+        })
+    }).await?;
+    let (end_time, end_time_txt) = unwrap_or_return!(discord_helper::await_right_reply(ctx, msg, parse_deal_end_time).await);
+
+    msg.channel_id.send_message(ctx, |m| {
+        m.embed(|e| {
+            e.description("その他、受け渡し方法などありましたらお書きください。\n長い場合、改行などをして**１回の送信**で書いてください。\n
+            何も無ければ「なし」で構いません。").color(0x4259fb)
+        })
+    }).await?;
+    let notice = unwrap_or_return!(discord_helper::await_right_reply(ctx, msg, |content| {
+        Ok(content.into())
+    }).await);
+
+    let new_deal = NewDealInfo {
+        channel_id: msg.channel_id.0 as i64, owner_id: msg.author.id.0 as i64, item, unit, price, end_time, notice,
+    };
+    let embed_editter = new_deal.info_embed(formats::display_name(&ctx, &msg.author, msg.guild(&ctx).await).await, end_time_txt, &units);
+
+    discord_helper::purge(&ctx, msg.channel_id, msg.id).await?;
+    msg.channel_id.send_message(&ctx, |m| {
+        m.embed(|e| {
+            e.title("これで出品します。よろしいですか？YES/NOで答えてください。(小文字でもOK。NOの場合初めからやり直してください。)");
+            embed_editter(e)
+        })
+    }).await?;
+    if !unwrap_or_return!(discord_helper::await_right_reply(&ctx, msg, |content| {
+        Ok(content.to_lowercase() == "yes")
+    }).await) {
+        msg.channel_id.say(&ctx, "初めからやり直してください。\n--------ｷﾘﾄﾘ線--------").await?;
+        discord_helper::purge(&ctx, msg.channel_id, msg.id).await?;
+        return Ok(());
+    }
+
+    discord_helper::purge(&ctx, msg.channel_id, msg.id).await?;
+    let new_deal: DealInfo = diesel::insert_into(deal_info).values(&new_deal).get_result(&conn)?;
+    let embed_message = msg.channel_id.send_message(&ctx, |m| {
+        m.embed(|e| {
+            e.title("出品内容").field("ID", new_deal.id, false);
+            embed_editter(e);
+            e.footer(|f| f.text(format!("!buy {} で購入できます", new_deal.id)))
+        })
+    }).await?;
+    diesel::update(deal_info).filter(deal_id_col.eq(new_deal.id)).set(embed_id.eq(Some(embed_message.id.0 as i64))).execute(&conn)?;
+
+    Ok(())
+}
+
+// チャンネルに掲載中の出品の一覧
+#[command]
+async fn deals(ctx: &Context, msg: &Message) -> CommandResult {
+    let conn = ctx.get_connection().await;
+
+    if !deal_manager::is_deal_channel(&conn, msg.channel_id)? {
+        msg.channel_id.say(&ctx.http, NOT_DEAL_CHANNEL).await?;
+        return Ok(());
+    }
+    let open = deal_manager::open_deals(&conn, msg.channel_id)?;
+    if open.is_empty() {
+        msg.channel_id.say(&ctx.http, "掲載中の出品はありません").await?;
+        return Ok(());
+    }
+    let mut lines = Vec::new();
+    for deal in &open {
+        let units = unit_manager::for_guild(&conn, msg.guild_id, &deal.unit)?;
+        lines.push(format!("ID {}: {} {}{} (出品者: <@{}>, 期限: {})",
+            deal.id, deal.item, deal.unit, units.with_raw(deal.price), deal.owner_id, formats::datetime_text(&deal.end_time)));
+    }
+    let description = lines.join("\n");
+    msg.channel_id.send_message(&ctx.http, |m| {
+        m.embed(|e| {
+            e.title("掲載中の出品").description(description).color(0x4259fb)
+        })
+    }).await?;
+    Ok(())
+}
+
+// 取引チャンネルでの`!buy 出品ID`。掲載中の出品が1件だけならIDを省略できる
+pub(super) async fn buy_deal(ctx: &Context, msg: &Message, args: Args) -> CommandResult {
+    let conn = ctx.get_connection().await;
+
+    let content = args.message().trim();
+    let deal_id = if content.is_empty() {
+        None
+    } else if let Ok(deal_id) = content.parse::<i32>() {
+        Some(deal_id)
+    } else {
+        msg.channel_id.say(&ctx.http, "`!buy 出品ID` の形式で入力してください").await?;
+        return Ok(());
+    };
+
+    match deal_manager::buy(&conn, msg.channel_id, deal_id, msg.author.id.0)? {
+        Ok(deal) => {
+            let units = unit_manager::for_guild(&conn, msg.guild_id, &deal.unit)?;
+            msg.channel_id.send_message(&ctx.http, |m| {
+                m.embed(|e| {
+                    e.description(format!("購入されました\n出品物: **{}**\n購入者: <@{}>\n価格: **{}{}**",
+                        deal.item, msg.author.id, deal.unit, units.with_raw(deal.price)))
+                     .footer(|f| f.text(format!("出品ID: {}", deal.id)))
+                     .color(0x4259fb)
+                })
+            }).await?;
+            deal_manager::close_embed(ctx, &deal).await?;
+            if let Ok(owner) = UserId(deal.owner_id as u64).to_user(ctx).await {
+                let _ = owner.direct_message(ctx, |m| {
+                    m.content(format!("{}(出品ID: {})が<@{}>に購入されました。受け渡しを行ってください。", deal.item, deal.id, msg.author.id))
+                }).await;
+            }
+        },
+        Err(error) => {
+            let text: String = match error {
+                BuyError::NotFound => "このチャンネルに該当する出品がありません".into(),
+                BuyError::Ambiguous(ids) => format!("掲載中の出品が複数あります。`!buy 出品ID`で指定してください (ID: {})",
+                    ids.iter().map(|id| id.to_string()).collect::<Vec<_>>().join(", ")),
+                BuyError::ByOwner => "自分の出品は購入できません".into(),
+                BuyError::Closed => "この出品は既に購入されたか、掲載期限を過ぎています".into(),
+            };
+            msg.channel_id.say(&ctx.http, text).await?;
+        }
+    }
+    Ok(())
+}
+
+// 取引チャンネルの登録・解除・一覧。チャンネルの管理権限が必要
+#[command]
+#[only_in(guilds)]
+#[required_permissions(MANAGE_CHANNELS)]
+#[sub_commands(deal_channel_add, deal_channel_remove, deal_channel_list)]
+async fn deal_channel(ctx: &Context, msg: &Message) -> CommandResult {
+    msg.channel_id.say(&ctx.http, "`!deal_channel add` このチャンネルを取引チャンネルに登録する\n\
+        `!deal_channel remove` このチャンネルの登録を解除する\n\
+        `!deal_channel list` このサーバーの取引チャンネルの一覧").await?;
+    Ok(())
+}
+
+#[command("add")]
+#[only_in(guilds)]
+#[required_permissions(MANAGE_CHANNELS)]
+async fn deal_channel_add(ctx: &Context, msg: &Message) -> CommandResult {
+    let conn = ctx.get_connection().await;
+
+    if channel_auction.find(msg.channel_id.0 as i64).first::<ChannelAuction>(&conn).optional()?.is_some() {
+        msg.channel_id.say(&ctx.http, "オークションチャンネルは取引チャンネルにできません").await?;
+        return Ok(());
+    }
+    let result = diesel::insert_into(channel_deal)
+        .values((deal_channel_col.eq(msg.channel_id.0 as i64), deal_guild_col.eq(msg.guild_id.map(|guild_id| guild_id.0 as i64))))
+        .on_conflict_do_nothing().execute(&conn)?;
+    if result == 0 {
+        msg.channel_id.say(&ctx.http, "このチャンネルは既に取引チャンネルです").await?;
+    } else {
+        msg.channel_id.say(&ctx.http, "このチャンネルを取引チャンネルに登録しました。`!sell`で出品できます").await?;
+    }
+    Ok(())
+}
+
+#[command("remove")]
+#[only_in(guilds)]
+#[required_permissions(MANAGE_CHANNELS)]
+async fn deal_channel_remove(ctx: &Context, msg: &Message) -> CommandResult {
+    let conn = ctx.get_connection().await;
+
+    if !deal_manager::is_deal_channel(&conn, msg.channel_id)? {
+        msg.channel_id.say(&ctx.http, "このチャンネルは取引チャンネルではありません").await?;
+        return Ok(());
+    }
+    let open = deal_manager::open_deals(&conn, msg.channel_id)?;
+    if !open.is_empty() {
+        msg.channel_id.say(&ctx.http, format!("掲載中の出品が{}件あるため解除できません", open.len())).await?;
+        return Ok(());
+    }
+    diesel::delete(channel_deal.find(msg.channel_id.0 as i64)).execute(&conn)?;
+    msg.channel_id.say(&ctx.http, "このチャンネルの取引チャンネルの登録を解除しました").await?;
+    Ok(())
+}
+
+#[command("list")]
+#[only_in(guilds)]
+#[required_permissions(MANAGE_CHANNELS)]
+async fn deal_channel_list(ctx: &Context, msg: &Message) -> CommandResult {
+    let conn = ctx.get_connection().await;
+
+    let registered = channel_deal.filter(deal_guild_col.eq(msg.guild_id.unwrap().0 as i64))
+        .order(deal_channel_col.asc()).load::<ChannelDeal>(&conn)?;
+    if registered.is_empty() {
+        msg.channel_id.say(&ctx.http, "このサーバーに取引チャンネルはありません").await?;
+        return Ok(());
+    }
+    let mut lines = Vec::new();
+    for row in &registered {
+        let open = deal_manager::open_deals(&conn, ChannelId(row.channel as u64))?;
+        lines.push(format!("<#{}> 掲載中の出品{}件", row.channel, open.len()));
+    }
+    let description = lines.join("\n");
+    msg.channel_id.send_message(&ctx.http, |m| {
+        m.embed(|e| {
+            e.title("取引チャンネル").description(description).color(0x4259fb)
+        })
+    }).await?;
+    Ok(())
+}
+
+
+#[group]
+#[commands(sell, deals, deal_channel)]
+struct Deal;
@@ -2,3 +2,9 @@ mod admin_only;
 pub use admin_only::ADMINONLY_GROUP;
 mod auction_deal;
 pub use auction_deal::AUCTIONDEAL_GROUP;
+mod settlement;
+pub use settlement::SETTLEMENT_GROUP;
+mod deal;
+pub use deal::DEAL_GROUP;
+mod unit;
+pub use unit::UNITSETTING_GROUP;
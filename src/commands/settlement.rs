@@ -0,0 +1,239 @@
+use serenity::prelude::*;
+use serenity::model::prelude::*;
+use serenity::framework::standard::{
+    macros::{group, command},
+    Args,
+    CommandResult,
+};
+use crate::utils::*;
+use crate::models::*;
+use crate::schema::auction_info::dsl::auction_info as auction_info_table;
+use diesel::prelude::*;
+
+fn settlement_error_text(error: SettlementError) -> &'static str {
+    match error {
+        SettlementError::NotFound => "指定された取引が見つかりません",
+        SettlementError::NotParty => "この取引の出品者・落札者ではないため確認できません",
+        SettlementError::AlreadyConfirmed => "既に確認済みです",
+        SettlementError::Disputed => "問題が報告されている取引です。モデレーターの対応を待ってください",
+        SettlementError::Defaulted => "落札者が放棄した取引です",
+    }
+}
+
+fn offer_error_text(error: OfferError) -> &'static str {
+    match error {
+        OfferError::NotFound => "指定された提示が見つかりません",
+        OfferError::NotBidder => "あなたへの提示ではありません",
+        OfferError::AlreadyResponded => "既に返答済みの提示です",
+        OfferError::Expired => "返答期限を過ぎています",
+    }
+}
+
+fn parse_id(args: &Args) -> Option<i32> {
+    args.message().trim().parse::<i32>().ok()
+}
+
+async fn confirm(ctx: &Context, msg: &Message, args: Args, confirmation: Confirmation) -> CommandResult {
+    let conn = ctx.get_connection().await;
+
+    let settlement_id = match parse_id(&args) {
+        Some(settlement_id) => settlement_id,
+        None => {
+            msg.channel_id.say(&ctx.http, "取引IDを数字で指定してください").await?;
+            return Ok(());
+        }
+    };
+    match settlement_manager::confirm(&conn, settlement_id, msg.author.id.0, confirmation)? {
+        Ok(settlement) => {
+            let content = if settlement.is_complete() {
+                format!("取引ID {} の受け渡しと支払いが確認され、取引が完了しました", settlement.id)
+            } else {
+                format!("取引ID {} を確認しました (状態: {})", settlement.id, settlement.status_text())
+            };
+            msg.channel_id.say(&ctx.http, content).await?;
+        },
+        Err(error) => {
+            msg.channel_id.say(&ctx.http, settlement_error_text(error)).await?;
+        }
+    }
+    Ok(())
+}
+
+// 出品者による受け渡しの確認。`!delivered 取引ID`
+#[command]
+async fn delivered(ctx: &Context, msg: &Message, args: Args) -> CommandResult {
+    confirm(ctx, msg, args, Confirmation::Delivered).await
+}
+
+// 落札者による支払いの確認。`!paid 取引ID`
+#[command]
+async fn paid(ctx: &Context, msg: &Message, args: Args) -> CommandResult {
+    confirm(ctx, msg, args, Confirmation::Paid).await
+}
+
+// 出品者か落札者による取引の問題の報告。`!dispute 取引ID 理由`
+#[command]
+async fn dispute(ctx: &Context, msg: &Message, args: Args) -> CommandResult {
+    let conn = ctx.get_connection().await;
+
+    let mut content = args.message().trim().splitn(2, char::is_whitespace);
+    let settlement_id = content.next().and_then(|id| id.parse::<i32>().ok());
+    let reason = content.next().map(str::trim).unwrap_or("");
+    let settlement_id = match settlement_id {
+        Some(settlement_id) if !reason.is_empty() => settlement_id,
+        _ => {
+            msg.channel_id.say(&ctx.http, "`!dispute 取引ID 理由` の形式で入力してください").await?;
+            return Ok(());
+        }
+    };
+    match settlement_manager::dispute(&conn, settlement_id, msg.author.id.0, reason)? {
+        Ok(settlement) => {
+            let info = auction_info_table.find(settlement.auction_id).first::<AuctionInfo>(&conn)?;
+            let _ = ChannelId(info.channel_id as u64).send_message(&ctx.http, |m| {
+                m.embed(|e| {
+                    e.title("取引の問題が報告されました")
+                        .description(format!("取引ID {}: {} (オークションID: {})\n出品者: <@{}> 落札者: <@{}>\n報告者: <@{}>\n理由: {}",
+                            settlement.id, info.item, info.id, settlement.seller_id, settlement.winner_id, msg.author.id, reason))
+                        .color(0xff4040)
+                })
+            }).await;
+            msg.channel_id.say(&ctx.http, format!("取引ID {} の問題を報告しました。モデレーターが確認します", settlement.id)).await?;
+        },
+        Err(SettlementError::Disputed) => {
+            msg.channel_id.say(&ctx.http, "この取引は既に問題が報告されています").await?;
+        },
+        Err(error) => {
+            msg.channel_id.say(&ctx.http, settlement_error_text(error)).await?;
+        }
+    }
+    Ok(())
+}
+
+// 期限を過ぎても完了していない取引と、問題が報告された取引の一覧
+#[command]
+#[required_permissions(MANAGE_MESSAGES)]
+async fn unsettled(ctx: &Context, msg: &Message) -> CommandResult {
+    let conn = ctx.get_connection().await;
+
+    let overdue = settlement_manager::overdue(&conn)?;
+    if overdue.is_empty() {
+        msg.channel_id.say(&ctx.http, "期限を過ぎた未完了の取引はありません").await?;
+        return Ok(());
+    }
+    let lines = overdue.iter().map(|(settlement, item)| {
+        format!("取引ID {}: {} (オークションID: {}) 出品者: <@{}> 落札者: <@{}> {}から / {}",
+            settlement.id, item, settlement.auction_id, settlement.seller_id, settlement.winner_id,
+            formats::datetime_text(&settlement.created_at), settlement.status_text())
+    }).collect::<Vec<_>>().join("\n");
+    msg.channel_id.send_message(&ctx.http, |m| {
+        m.embed(|e| {
+            e.title("未完了の取引").description(lines).color(0xffaf60)
+        })
+    }).await?;
+    Ok(())
+}
+
+
+// 出品者かモデレーターが落札者の放棄を記録し、次点の入札者に出品物を提示する。`!default 取引ID`
+#[command]
+async fn default(ctx: &Context, msg: &Message, args: Args) -> CommandResult {
+    let settlement_id = match parse_id(&args) {
+        Some(settlement_id) => settlement_id,
+        None => {
+            msg.channel_id.say(&ctx.http, "取引IDを数字で指定してください").await?;
+            return Ok(());
+        }
+    };
+    let is_moderator = discord_helper::is_moderator(ctx, msg).await;
+    let conn = ctx.get_connection().await;
+
+    let source = match settlement_manager::mark_defaulted(&conn, settlement_id, msg.author.id.0, is_moderator)? {
+        Ok(source) => source,
+        Err(error) => {
+            let text = match error {
+                DefaultError::NotFound => "指定された取引が見つかりません",
+                DefaultError::NotSeller => "出品者かモデレーターのみ放棄を記録できます",
+                DefaultError::AlreadyPaid => "支払いが確認された取引は放棄にできません",
+                DefaultError::AlreadyDefaulted => "既に放棄が記録されています",
+            };
+            msg.channel_id.say(&ctx.http, text).await?;
+            return Ok(());
+        }
+    };
+    let info = auction_info_table.find(source.auction_id).first::<AuctionInfo>(&conn)?;
+    let units = unit_manager::for_channel(&conn, ChannelId(info.channel_id as u64), &info.unit)?;
+    let offer = settlement_manager::offer_next(&conn, &source)?;
+    match offer {
+        Some(offer) => {
+            msg.channel_id.say(&ctx.http, format!("取引ID {} の放棄を記録しました。次点の入札者<@{}>に{}{}で提示しました",
+                source.id, offer.bidder_id, info.unit, units.with_raw(offer.price))).await?;
+            settlement_manager::announce_offer(ctx, &info, &units, &source, Some(&offer)).await;
+        },
+        None => {
+            msg.channel_id.say(&ctx.http, format!("取引ID {} の放棄を記録しました。提示できる次点の入札者はいません", source.id)).await?;
+        }
+    }
+    Ok(())
+}
+
+// 次点の入札者が提示を承諾する。`!accept 提示ID`
+#[command]
+async fn accept(ctx: &Context, msg: &Message, args: Args) -> CommandResult {
+    let offer_id = match parse_id(&args) {
+        Some(offer_id) => offer_id,
+        None => {
+            msg.channel_id.say(&ctx.http, "提示IDを数字で指定してください").await?;
+            return Ok(());
+        }
+    };
+    let conn = ctx.get_connection().await;
+
+    let (offer, created) = match settlement_manager::accept(&conn, offer_id, msg.author.id.0)? {
+        Ok(accepted) => accepted,
+        Err(error) => {
+            msg.channel_id.say(&ctx.http, offer_error_text(error)).await?;
+            return Ok(());
+        }
+    };
+    let info = auction_info_table.find(offer.auction_id).first::<AuctionInfo>(&conn)?;
+    let content = format!("{}(オークションID: {})の次点の提示が承諾されました。取引ID: **{}**\n\
+        出品者は受け渡し後に`!delivered {2}`、落札者は支払い後に`!paid {2}`を送ってください。\n\
+        問題があれば`!dispute {2} 理由`で報告できます。", info.item, info.id, created.id);
+    msg.channel_id.say(&ctx.http, &content).await?;
+    if let Ok(seller) = UserId(created.seller_id as u64).to_user(ctx).await {
+        let _ = seller.direct_message(ctx, |m| m.content(&content)).await;
+    }
+    Ok(())
+}
+
+// 次点の入札者が提示を辞退する。次の入札者がいれば続けて提示する。`!decline 提示ID`
+#[command]
+async fn decline(ctx: &Context, msg: &Message, args: Args) -> CommandResult {
+    let offer_id = match parse_id(&args) {
+        Some(offer_id) => offer_id,
+        None => {
+            msg.channel_id.say(&ctx.http, "提示IDを数字で指定してください").await?;
+            return Ok(());
+        }
+    };
+    let conn = ctx.get_connection().await;
+
+    let offer = match settlement_manager::decline(&conn, offer_id, msg.author.id.0)? {
+        Ok(offer) => offer,
+        Err(error) => {
+            msg.channel_id.say(&ctx.http, offer_error_text(error)).await?;
+            return Ok(());
+        }
+    };
+    msg.channel_id.say(&ctx.http, "提示を辞退しました").await?;
+    let (source, info) = settlement_manager::source_of(&conn, &offer)?;
+    let units = unit_manager::for_channel(&conn, ChannelId(info.channel_id as u64), &info.unit)?;
+    let next = settlement_manager::offer_next(&conn, &source)?;
+    settlement_manager::announce_offer(ctx, &info, &units, &source, next.as_ref()).await;
+    Ok(())
+}
+
+
+#[group]
+#[commands(delivered, paid, dispute, unsettled, default, accept, decline)]
+struct Settlement;
@@ -0,0 +1,141 @@
+use serenity::prelude::*;
+use serenity::model::prelude::*;
+use serenity::framework::standard::{
+    macros::{group, command},
+    Args,
+    CommandResult,
+};
+use crate::utils::*;
+use crate::models::*;
+
+const UNIT_HELP: &str = "`!unit set [通貨=通貨名] 表示名 倍率 [別名...]` 単位を追加・変更する (ex. `!unit set sb 27st シュルカーボックス`)\n\
+    `!unit remove [通貨=通貨名] 表示名` 追加した単位を削除する\n\
+    `!unit list [通貨名]` 使える単位の一覧\n\
+    通貨を指定すると、その単位のオークション・取引でのみ使われます (ex. `!unit set 通貨=エンダーパール st 16`)";
+
+// `通貨=通貨名`が先頭にあれば取り出す。なければサーバー全体の定義(空文字列)
+fn split_currency(args: &str) -> (String, Vec<&str>) {
+    let mut words = args.split_whitespace().collect::<Vec<_>>();
+    match words.first().copied().and_then(|word| word.strip_prefix("通貨=")) {
+        Some(currency) => {
+            let currency = currency.to_string();
+            words.remove(0);
+            (currency, words)
+        },
+        None => (String::new(), words),
+    }
+}
+
+// 価格の入力と区別できる名前のみ単位にできる
+fn valid_unit_name(name: &str) -> bool {
+    !name.is_empty() && name != "個" && !name.chars().any(|c| c.is_ascii_digit() || c == '+' || c == '.' || c == '=')
+}
+
+// サーバーの価格の単位の設定
+#[command]
+#[only_in(guilds)]
+#[sub_commands(unit_set, unit_remove, unit_list)]
+async fn unit(ctx: &Context, msg: &Message) -> CommandResult {
+    msg.channel_id.say(&ctx.http, UNIT_HELP).await?;
+    Ok(())
+}
+
+#[command("set")]
+#[only_in(guilds)]
+#[required_permissions(MANAGE_GUILD)]
+async fn unit_set(ctx: &Context, msg: &Message, args: Args) -> CommandResult {
+    let conn = ctx.get_connection().await;
+
+    let (currency, words) = split_currency(args.message());
+    let (label, multiplier_text, aliases) = match &words[..] {
+        [label, multiplier_text, aliases @ ..] => (*label, *multiplier_text, aliases),
+        _ => {
+            msg.channel_id.say(&ctx.http, UNIT_HELP).await?;
+            return Ok(());
+        }
+    };
+    if let Some(name) = std::iter::once(&label).chain(aliases.iter()).find(|name| !valid_unit_name(name)) {
+        msg.channel_id.say(&ctx.http, format!("「{}」は単位の名前にできません。数字や`+`、`.`、`=`を含まない名前にしてください", name)).await?;
+        return Ok(());
+    }
+    // 倍率は`27st`のように既存の単位でも指定できる
+    let units = unit_manager::for_guild(&conn, msg.guild_id, &currency)?;
+    let multiplier = match units.parse(multiplier_text) {
+        Ok(multiplier) if multiplier > i32::MAX as i64 => {
+            msg.channel_id.say(&ctx.http, format!("倍率が大きすぎます (上限は{}個です)", i32::MAX)).await?;
+            return Ok(());
+        },
+        Ok(multiplier) if multiplier >= 1 => multiplier as i32,
+        Ok(_) => {
+            msg.channel_id.say(&ctx.http, "倍率は1個以上にしてください").await?;
+            return Ok(());
+        },
+        Err(error) => {
+            msg.channel_id.say(&ctx.http, format!("倍率の{}", error)).await?;
+            return Ok(());
+        }
+    };
+
+    let definition = unit_manager::set(&conn, &NewUnitDefinition {
+        guild_id: msg.guild_id.unwrap().0 as i64,
+        currency: currency.clone(),
+        label: label.to_string(),
+        aliases: aliases.iter().map(|alias| alias.to_string()).collect(),
+        multiplier,
+    })?;
+    let target = if currency.is_empty() { "このサーバー".into() } else { format!("通貨「{}」", currency) };
+    msg.channel_id.say(&ctx.http, format!("{}の単位に{} = {}個を設定しました", target, definition.label, definition.multiplier)).await?;
+    Ok(())
+}
+
+#[command("remove")]
+#[only_in(guilds)]
+#[required_permissions(MANAGE_GUILD)]
+async fn unit_remove(ctx: &Context, msg: &Message, args: Args) -> CommandResult {
+    let conn = ctx.get_connection().await;
+
+    let (currency, words) = split_currency(args.message());
+    let label = match &words[..] {
+        [label] => *label,
+        _ => {
+            msg.channel_id.say(&ctx.http, UNIT_HELP).await?;
+            return Ok(());
+        }
+    };
+    if unit_manager::remove(&conn, msg.guild_id.unwrap(), &currency, label)? == 0 {
+        msg.channel_id.say(&ctx.http, "指定された単位は追加されていません (既定の単位は削除できません)").await?;
+    } else {
+        msg.channel_id.say(&ctx.http, format!("単位{}を削除しました", label)).await?;
+    }
+    Ok(())
+}
+
+#[command("list")]
+#[only_in(guilds)]
+async fn unit_list(ctx: &Context, msg: &Message, args: Args) -> CommandResult {
+    let conn = ctx.get_connection().await;
+
+    let currency = args.message().trim();
+    let units = unit_manager::for_guild(&conn, msg.guild_id, currency)?;
+    let definitions = unit_manager::list(&conn, msg.guild_id.unwrap())?;
+    let currencies = definitions.iter()
+        .filter(|definition| !definition.currency.is_empty())
+        .map(|definition| definition.currency.as_str())
+        .collect::<std::collections::BTreeSet<_>>();
+    let title = if currency.is_empty() { "このサーバーの単位".into() } else { format!("通貨「{}」の単位", currency) };
+    msg.channel_id.send_message(&ctx.http, |m| {
+        m.embed(|e| {
+            e.title(title).description(units.describe()).color(0xffaf60);
+            if !currencies.is_empty() {
+                e.field("通貨ごとの単位がある通貨", currencies.into_iter().collect::<Vec<_>>().join(", "), false);
+            }
+            e
+        })
+    }).await?;
+    Ok(())
+}
+
+
+#[group]
+#[commands(unit)]
+struct UnitSetting;
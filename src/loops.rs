@@ -1,9 +1,9 @@
 use std::sync::Arc;
 use serenity::prelude::*;
-use serenity::model::id::{ChannelId, UserId};
+use serenity::model::id::{ChannelId, GuildId, UserId};
 use tokio;
 use std::time::Duration;
-use chrono::{Local, Timelike};
+use chrono::{Utc, Timelike};
 use crate::utils::*;
 use crate::schema::channel_auction::dsl::channel_auction;
 use crate::models::*;
@@ -12,49 +12,228 @@ use diesel::prelude::*;
 pub async fn start_check_minutely(ctx: Arc<Context>) {
     tokio::spawn(async move {
         loop {
-            let now = Local::now().naive_local();
-            
-            let conn = ctx.get_connection().await;
-
-            let result = channel_auction.get_results::<ChannelAuction>(&conn).unwrap();
-            for ChannelAuction { channel, auction: auction_id } in result.into_iter() {
-                let auction_id = match auction_id {
-                    Some(auction_id) => auction_id,
-                    None => continue,
-                };
-                let manager = AuctionManager::from_id(&conn, auction_id).unwrap().unwrap();
-                
-                if !(manager.end_time <= now) {
-                    continue;
-                }
-
-                if let [.., last_tend] = &manager.tend[..] {
-                    let owner_name = formats::display_name(&ctx, &UserId(manager.owner_id).to_user(&ctx.http).await.unwrap(), None).await;
-                    let last_tender_name = formats::display_name(&ctx, &UserId(last_tend.tender_id).to_user(&ctx.http).await.unwrap(), None).await;
-                    let _ = ChannelId(channel as u64).send_message(
-                        &ctx, |m| {
-                            m.embed(|e| {
-                                e.description(format!("{}が出品した{}を{}が{}{}で落札しました！",
-                                    owner_name, manager.item, last_tender_name, manager.unit, formats::stack_with_raw(last_tend.price)))
-                            })
+            // 1件の失敗で他のオークションや取引の処理が止まらないよう、エラーは表示して次に進む
+            match load_auction_channels(&ctx).await {
+                Ok(result) => {
+                    for ChannelAuction { channel, auction: auction_id, .. } in result.into_iter() {
+                        let auction_id = match auction_id {
+                            Some(auction_id) => auction_id,
+                            None => continue,
+                        };
+                        if let Err(why) = check_auction(&ctx, ChannelId(channel as u64), auction_id).await {
+                            println!("Error checking auction {}: {:?}", auction_id, why);
                         }
-                    ).await;
-                } else {
-                    let _ = ChannelId(channel as u64).send_message(
-                        &ctx, |m| {
-                            m.embed(|e| {
-                                e.description("入札者はいませんでした")
-                            })
-                        }
-                    ).await;
-                };
-                ChannelId(channel as u64).say(&ctx, "--------ｷﾘﾄﾘ線--------").await.unwrap();
-                manager.finish(&ctx).await;
+                    }
+                },
+                Err(why) => println!("Error loading auction channels: {:?}", why),
+            }
+
+            if let Err(why) = remind_settlements(&ctx).await {
+                println!("Error reminding settlements: {:?}", why);
+            }
+            if let Err(why) = pass_expired_offers(&ctx).await {
+                println!("Error passing expired offers: {:?}", why);
+            }
+            if let Err(why) = expire_deals(&ctx).await {
+                println!("Error expiring deals: {:?}", why);
             }
 
             // 00秒まで待機
-            let now = Local::now().naive_local();
+            let now = Utc::now();
             tokio::time::sleep(Duration::from_secs(60-now.second() as u64)).await;
         }
     });
-}
\ No newline at end of file
+}
+
+// サーバーを記録する前に登録されたチャンネルにも、サーバーの単位の定義が使われるようにする
+pub async fn record_channel_guilds(ctx: &Context, guilds: &[GuildId]) -> TaskResult {
+    for guild in guilds {
+        let channels = guild.channels(&ctx.http).await?.keys().map(|channel| channel.0 as i64).collect::<Vec<_>>();
+        let conn = ctx.pool().await.get()?;
+        unit_manager::record_guild(&conn, *guild, &channels)?;
+    }
+    Ok(())
+}
+
+async fn load_auction_channels(ctx: &Context) -> Result<Vec<ChannelAuction>, Box<dyn std::error::Error + Send + Sync>> {
+    let conn = ctx.pool().await.get()?;
+    Ok(channel_auction.get_results::<ChannelAuction>(&conn)?)
+}
+
+// 開催中のオークションの値下げ・表示の更新と、終了したオークションの終了処理
+// 中止の告知の後に終了処理が失敗したオークションも、ここで終了処理する
+async fn check_auction(ctx: &Context, channel: ChannelId, auction_id: i32) -> TaskResult {
+    let conn = ctx.pool().await.get()?;
+    let mut manager = match AuctionManager::from_id(&conn, auction_id)? {
+        Ok(manager) => manager,
+        Err(why) => return Err(format!("{:?}", why).into()),
+    };
+
+    if manager.status == AuctionStatus::Active && !manager.is_over() {
+        // ダッチオークションは値下げ時刻を過ぎていれば価格を下げる
+        let dropped = manager.drop_price(&conn)?;
+        if let Some(price) = dropped {
+            let _ = channel.send_message(
+                ctx, |m| {
+                    m.embed(|e| {
+                        e.description(format!("価格が**{}{}**に下がりました", manager.unit, manager.units.with_raw(price)))
+                         .color(0xffaf60)
+                    })
+                }
+            ).await;
+        }
+        // 入札による変更は入札時に表示しているので、ここでは値下げした時と、残り時間の表示が前回(1分前)から変わった時だけ編集する
+        let now = Utc::now();
+        let remaining_changed = formats::remaining_text(manager.end_time - now)
+            != formats::remaining_text(manager.end_time - (now - chrono::Duration::minutes(1)));
+        if dropped.is_some() || remaining_changed {
+            let _ = manager.update_embed(ctx).await;
+        }
+        return Ok(());
+    }
+
+    // 即決などで既に終了処理されていれば何もしない
+    let (status, settlements) = match manager.finish(&conn)? {
+        Some(finished) => finished,
+        None => return Ok(()),
+    };
+
+    if status == AuctionStatus::Cancelled {
+        let _ = channel.send_message(
+            ctx, |m| {
+                m.embed(|e| {
+                    e.description(format!("{}は中止されました", manager.item))
+                     .footer(|f| f.text(format!("ID: {}", manager.id)))
+                     .color(0xffaf60)
+                })
+            }
+        ).await;
+    } else {
+        // 封印入札は終了時に全順位を公開する
+        if manager.auction_type == AuctionType::Sealed && !manager.tend.is_empty() {
+            let ranking = manager.ranking().iter().enumerate().map(|(i, tend)| {
+                format!("{}位: **{}** {}{}", i + 1, tend.tender_name, manager.unit, manager.units.with_raw(tend.price))
+            }).collect::<Vec<_>>().join("\n");
+            let _ = channel.send_message(
+                ctx, |m| {
+                    m.embed(|e| {
+                        e.title("封印入札の結果").description(ranking)
+                    })
+                }
+            ).await;
+        }
+        // 記録は済んでいるので、告知に失敗しても取引の通知と次のオークションの開始は行う
+        if let Err(why) = announce_result(ctx, channel, &manager).await {
+            println!("Error announcing result of auction {}: {:?}", manager.id, why);
+        }
+    }
+    let _ = channel.say(ctx, "--------ｷﾘﾄﾘ線--------").await;
+    manager.after_finish(ctx, status, &settlements).await
+}
+
+// 終了したオークションの落札者を告知する
+async fn announce_result(ctx: &Context, channel: ChannelId, manager: &AuctionManager) -> TaskResult {
+    if manager.quantity > 1 {
+        let winners = manager.winners();
+        let description = if winners.is_empty() && manager.tend.is_empty() {
+            "入札者はいませんでした".to_string()
+        } else if winners.is_empty() {
+            format!("最低落札価格以上の入札がなかったため、{}は不成立となりました", manager.item)
+        } else {
+            let owner_name = formats::display_name(ctx, &UserId(manager.owner_id).to_user(&ctx.http).await?, None).await;
+            let lines = winners.iter().map(|(tend, quantity)| {
+                format!("**{}**: {}{} × {}個", tend.tender_name, manager.unit, manager.units.with_raw(tend.price), quantity)
+            }).collect::<Vec<_>>().join("\n");
+            let mut description = format!("{}が出品した{}({}個)の落札者が決まりました！\n{}", owner_name, manager.item, manager.quantity, lines);
+            let sold = winners.iter().map(|(_, quantity)| quantity).sum::<i32>();
+            if sold < manager.quantity {
+                description.push_str(&format!("\n残りの{}個は売れ残りました", manager.quantity - sold));
+            }
+            description
+        };
+        let _ = channel.send_message(
+            ctx, |m| {
+                m.embed(|e| {
+                    e.description(description)
+                })
+            }
+        ).await;
+    } else if !manager.tend.is_empty() && !manager.reserve_met() {
+        let owner_name = formats::display_name(ctx, &UserId(manager.owner_id).to_user(&ctx.http).await?, None).await;
+        let _ = channel.send_message(
+            ctx, |m| {
+                m.embed(|e| {
+                    e.description(format!("最高入札額が最低落札価格に達しなかったため、{}が出品した{}は不成立となりました",
+                        owner_name, manager.item))
+                })
+            }
+        ).await;
+    } else if let Some(winner) = manager.highest_tend() {
+        let owner_name = formats::display_name(ctx, &UserId(manager.owner_id).to_user(&ctx.http).await?, None).await;
+        let winner_name = formats::display_name(ctx, &UserId(winner.tender_id).to_user(&ctx.http).await?, None).await;
+        let price = manager.settlement_price().unwrap_or(winner.price);
+        let _ = channel.send_message(
+            ctx, |m| {
+                m.embed(|e| {
+                    e.description(format!("{}が出品した{}を{}が{}{}で落札しました！",
+                        owner_name, manager.item, winner_name, manager.unit, manager.units.with_raw(price)))
+                })
+            }
+        ).await;
+    } else {
+        let _ = channel.send_message(
+            ctx, |m| {
+                m.embed(|e| {
+                    e.description("入札者はいませんでした")
+                })
+            }
+        ).await;
+    }
+    Ok(())
+}
+
+// 完了していない取引のリマインド
+async fn remind_settlements(ctx: &Context) -> TaskResult {
+    let conn = ctx.pool().await.get()?;
+    for (settlement, item) in settlement_manager::due_reminders(&conn)? {
+        let pending = [
+            (settlement.seller_id, settlement.delivered_at.is_none(), format!("受け渡しが済んだら`!delivered {}`", settlement.id)),
+            (settlement.winner_id, settlement.paid_at.is_none(), format!("支払いが済んだら`!paid {}`", settlement.id)),
+        ];
+        for (user_id, is_pending, command) in pending.iter() {
+            if !is_pending {
+                continue;
+            }
+            if let Ok(user) = UserId(*user_id as u64).to_user(&ctx.http).await {
+                let _ = user.direct_message(ctx, |m| {
+                    m.content(format!("{}(取引ID: {})の取引が完了していません。{}を送ってください。\n\
+                        問題があれば`!dispute {} 理由`で報告できます。", item, settlement.id, command, settlement.id))
+                }).await;
+            }
+        }
+        settlement_manager::mark_reminded(&conn, settlement.id)?;
+    }
+    Ok(())
+}
+
+// 返答期限を過ぎた次点の提示は次の入札者に回す
+async fn pass_expired_offers(ctx: &Context) -> TaskResult {
+    let conn = ctx.pool().await.get()?;
+    for offer in settlement_manager::expire_offers(&conn)? {
+        let (source, info) = settlement_manager::source_of(&conn, &offer)?;
+        let units = unit_manager::for_channel(&conn, ChannelId(info.channel_id as u64), &info.unit)?;
+        let next = settlement_manager::offer_next(&conn, &source)?;
+        settlement_manager::announce_offer(ctx, &info, &units, &source, next.as_ref()).await;
+    }
+    Ok(())
+}
+
+// 掲載期限を過ぎた取引チャンネルの出品
+async fn expire_deals(ctx: &Context) -> TaskResult {
+    let conn = ctx.pool().await.get()?;
+    for deal in deal_manager::expire(&conn)? {
+        let _ = deal_manager::close_embed(ctx, &deal).await;
+        let _ = ChannelId(deal.channel_id as u64).say(ctx, format!("{}(出品ID: {})は掲載期限を過ぎたため終了しました", deal.item, deal.id)).await;
+    }
+    Ok(())
+}
@@ -63,7 +63,7 @@ impl TypeMapKey for CommandCounter {
 }
 
 
-use kgx_rust::loops::start_check_minutely;
+use kgx_rust::loops::{start_check_minutely, record_channel_guilds};
 
 struct Handler;
 
@@ -72,6 +72,10 @@ impl EventHandler for Handler {
     async fn ready(&self, ctx: Context, ready: Ready) {
         println!("{} is connected!", ready.user.name);
         let _ = ChannelId(928684833595613226).say(&ctx.http, "起動しました").await;
+        let guilds = ready.guilds.iter().map(|guild| guild.id()).collect::<Vec<_>>();
+        if let Err(why) = record_channel_guilds(&ctx, &guilds).await {
+            println!("Error recording channel guilds: {:?}", why);
+        }
         start_check_minutely(Arc::new(ctx)).await;
     }
 }
@@ -348,7 +352,10 @@ async fn main() {
         .group(&MATH_GROUP)
         .group(&OWNER_GROUP)
         .group(&ADMINONLY_GROUP)
-        .group(&AUCTIONDEAL_GROUP);
+        .group(&AUCTIONDEAL_GROUP)
+        .group(&SETTLEMENT_GROUP)
+        .group(&DEAL_GROUP)
+        .group(&UNITSETTING_GROUP);
 
     let mut client = Client::builder(&token)
         .event_handler(Handler)
@@ -510,7 +517,13 @@ async fn about(ctx: &Context, msg: &Message) -> CommandResult {
 #[command]
 #[aliases("sc")]
 async fn stack_check(ctx: &Context, msg: &Message, value: Args) -> CommandResult{
-    msg.channel_id.say(&ctx.http, stack_check_fn(value.message())).await?;
+    // サーバー全体の単位の定義で読み取る
+    let units = unit_manager::for_guild(&ctx.get_connection().await, msg.guild_id, "")?;
+    let content = match stack_check_fn(value.message(), &units) {
+        Ok(price) => format!("{} ({})", price, units.to_stack(price)),
+        Err(error) => error.to_string(),
+    };
+    msg.channel_id.say(&ctx.http, content).await?;
 
     Ok(())
 }
@@ -1,6 +1,6 @@
-use chrono::NaiveDateTime;
-use crate::schema::auction_info as info_table;
-use crate::utils::formats;
+use chrono::{DateTime, Utc};
+use crate::schema::{auction_info as info_table, tend_history as history_table, proxy_tend as proxy_table, dutch_schedule as dutch_table, auction_edit_log as edit_log_table, settlement as settlement_table, second_chance_offer as offer_table, deal_info as deal_table, unit_definition as unit_table};
+use crate::utils::formats::{self, UnitSystem};
 
 #[derive(Queryable, Debug)]
 pub struct AuctionInfo {
@@ -8,14 +8,65 @@ pub struct AuctionInfo {
     pub channel_id: i64,
     pub owner_id: i64,
     pub item: String,
-    pub end_time: NaiveDateTime,
-    pub start_price: i32,
-    pub bin_price: Option<i32>,
+    pub end_time: DateTime<Utc>,
+    pub start_price: i64,
+    pub bin_price: Option<i64>,
     pub unit: String,
     pub embed_id: Option<i64>, // embed送信前のみNoneにしてよい
     pub notice: String,
-    pub tenders_id: Vec<i64>,
-    pub tends_price: Vec<i32>,
+    pub min_increment: Option<i64>,
+    pub min_increment_percent: Option<i32>,
+    pub extension_window: Option<i32>,
+    pub extension_minutes: Option<i32>,
+    pub status: String,
+    pub winner_id: Option<i64>,
+    pub final_price: Option<i64>,
+    pub finished_at: Option<DateTime<Utc>>,
+    pub reserve_price: Option<i64>, // 出品者以外には公開しない
+    pub auction_type: String,
+    pub second_price: bool,
+    pub quantity: i32,
+    pub duration_minutes: Option<i32>, // 順番待ちの間のみ使う
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum AuctionStatus {
+    Queued,
+    Active,
+    Sold,
+    Unsold,
+    BoughtOut,
+    Cancelled,
+}
+
+impl AuctionStatus {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Queued => "queued",
+            Self::Active => "active",
+            Self::Sold => "sold",
+            Self::Unsold => "unsold",
+            Self::BoughtOut => "bought_out",
+            Self::Cancelled => "cancelled",
+        }
+    }
+
+    pub fn parse(text: &str) -> Option<Self> {
+        match text {
+            "queued" => Some(Self::Queued),
+            "active" => Some(Self::Active),
+            "sold" => Some(Self::Sold),
+            "unsold" => Some(Self::Unsold),
+            "bought_out" => Some(Self::BoughtOut),
+            "cancelled" => Some(Self::Cancelled),
+            _ => None,
+        }
+    }
+
+    // 落札者が決まった状態
+    pub fn has_winner(&self) -> bool {
+        matches!(self, Self::Sold | Self::BoughtOut)
+    }
 }
 
 #[derive(Insertable, Debug, Clone)]
@@ -24,38 +75,153 @@ pub struct NewAuctionInfo {
     pub channel_id: i64,
     pub owner_id: i64,
     pub item: String,
-    pub end_time: NaiveDateTime,
-    pub start_price: i32,
-    pub bin_price: Option<i32>,
+    pub end_time: DateTime<Utc>,
+    pub start_price: i64,
+    pub bin_price: Option<i64>,
     pub unit: String,
     pub notice: String,
+    pub min_increment: Option<i64>,
+    pub min_increment_percent: Option<i32>,
+    pub extension_window: Option<i32>,
+    pub extension_minutes: Option<i32>,
+    pub reserve_price: Option<i64>,
+    pub auction_type: String,
+    pub second_price: bool,
+    pub quantity: i32,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum AuctionType {
+    English,
+    Dutch,
+    Sealed,
+}
+
+impl AuctionType {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::English => "english",
+            Self::Dutch => "dutch",
+            Self::Sealed => "sealed",
+        }
+    }
+
+    pub fn parse(text: &str) -> Option<Self> {
+        match text {
+            "english" => Some(Self::English),
+            "dutch" => Some(Self::Dutch),
+            "sealed" => Some(Self::Sealed),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub enum MinIncrement {
+    Amount(i64),
+    Percent(i32),
+}
+
+impl MinIncrement {
+    pub fn from_columns(amount: Option<i64>, percent: Option<i32>) -> Option<Self> {
+        match (amount, percent) {
+            (Some(amount), _) => Some(Self::Amount(amount)),
+            (None, Some(percent)) => Some(Self::Percent(percent)),
+            (None, None) => None,
+        }
+    }
+
+    pub fn into_columns(this: Option<Self>) -> (Option<i64>, Option<i32>) {
+        match this {
+            Some(Self::Amount(amount)) => (Some(amount), None),
+            Some(Self::Percent(percent)) => (None, Some(percent)),
+            None => (None, None),
+        }
+    }
+
+    // priceからの最低上げ幅(1個未満は切り上げ)
+    pub fn step(&self, price: i64) -> i64 {
+        match *self {
+            Self::Amount(amount) => amount,
+            Self::Percent(percent) => ((price as i128 * percent as i128 + 99) / 100).max(1).min(i64::MAX as i128) as i64,
+        }
+    }
+
+    pub fn describe(&self, unit: &str, units: &UnitSystem) -> String {
+        match *self {
+            Self::Amount(amount) => format!("{}{}", unit, units.with_raw(amount)),
+            Self::Percent(percent) => format!("現在価格の{}%", percent),
+        }
+    }
+}
+
+// 終了window分前以降の入札で、終了日時を入札のminutes分後まで延長する
+#[derive(Debug, Clone, Copy)]
+pub struct EndExtension {
+    pub window: i32,
+    pub minutes: i32,
+}
+
+impl EndExtension {
+    pub fn from_columns(window: Option<i32>, minutes: Option<i32>) -> Option<Self> {
+        match (window, minutes) {
+            (Some(window), Some(minutes)) => Some(Self { window, minutes }),
+            _ => None,
+        }
+    }
+
+    pub fn into_columns(this: Option<Self>) -> (Option<i32>, Option<i32>) {
+        match this {
+            Some(Self { window, minutes }) => (Some(window), Some(minutes)),
+            None => (None, None),
+        }
+    }
+
+    pub fn describe(&self) -> String {
+        format!("終了{}分前以降の入札で{}分延長", self.window, self.minutes)
+    }
 }
 
 use serenity::builder::CreateEmbed;
 impl NewAuctionInfo {
-    pub fn info_embed(&self, tender: String, end_time: String) -> impl Fn(&mut CreateEmbed) -> &mut CreateEmbed {
+    // current_priceは現在の最高入札額。最低落札価格は金額を出さず、達したかどうかだけを表示する
+    pub fn info_embed(&self, tender: String, end_time: String, current_price: Option<i64>, units: &UnitSystem) -> impl Fn(&mut CreateEmbed) -> &mut CreateEmbed {
         let item = self.item.clone();
         let unit = self.unit.clone();
-        let mut start_price = format!("{}{}", unit, formats::int_to_stack(self.start_price));
-        if self.start_price >= 64 {
-            start_price.push_str(&format!(" ({})", self.start_price));
-        }
-        let bin_price = if let Some(raw_bin_price) = self.bin_price {
-            let mut bin_price = format!("{}{}", self.unit, formats::int_to_stack(raw_bin_price));
-            if raw_bin_price >= 64 {
-                bin_price.push_str(&format!(" ({})", raw_bin_price));
-            }
-            bin_price
+        let start_price = format!("{}{}", unit, units.with_raw(self.start_price));
+        let bin_price = self.bin_price.map_or_else(|| "なし".into(), |bin_price| format!("{}{}", self.unit, units.with_raw(bin_price)));
+        let min_increment = MinIncrement::from_columns(self.min_increment, self.min_increment_percent)
+            .map_or_else(|| "なし".into(), |min_increment| min_increment.describe(&self.unit, units));
+        let extension = EndExtension::from_columns(self.extension_window, self.extension_minutes)
+            .map_or_else(|| "なし".into(), |extension| extension.describe());
+        let reserve = match (self.reserve_price, current_price) {
+            (None, _) => "なし",
+            (Some(reserve_price), Some(current_price)) if current_price >= reserve_price => "達成済み",
+            (Some(_), _) => "未達",
+        };
+        let auction_type = match (AuctionType::parse(&self.auction_type), self.second_price) {
+            (Some(AuctionType::Dutch), _) => "ダッチオークション",
+            (Some(AuctionType::Sealed), false) => "封印入札 (最高額の入札者が自分の入札額を支払う)",
+            (Some(AuctionType::Sealed), true) => "封印入札 (最高額の入札者が2番目の入札額+最低入札単位を支払う)",
+            _ => "通常",
+        };
+        let quantity = if self.quantity > 1 {
+            format!("{}個 (入札額は1個あたりの価格)", self.quantity)
         } else {
-            "なし".into()
+            "1個".into()
         };
         let notice = self.notice.clone();
         move |e| {
             e.field("出品者", &tender, true)
              .field("出品物", &item, true)
+             .field("数量", &quantity, true)
+             .field("形式", auction_type, false)
              .field("開始価格", &start_price, false)
              .field("即決価格", &bin_price, false)
+             .field("最低入札単位", &min_increment, false)
+             .field("最低落札価格", reserve, false)
              .field("終了日時", &end_time, true)
+             .field("自動延長", &extension, true)
              .field("特記事項", &notice, true)
              .color(0xffaf60)
         }
@@ -66,4 +232,340 @@ impl NewAuctionInfo {
 pub struct ChannelAuction {
     pub channel: i64,
     pub auction: Option<i32>,
+    pub extension_window: Option<i32>,
+    pub extension_minutes: Option<i32>,
+    pub settlement_remind_hours: i32,
+    pub guild_id: Option<i64>, // 登録時に記録する。単位の定義を引くのに使う
+}
+
+
+#[derive(Queryable, Debug)]
+pub struct TendHistory {
+    pub id: i32,
+    pub auction_id: i32,
+    pub tender_id: i64,
+    pub price: i64,
+    pub tended_at: DateTime<Utc>,
+    pub message_id: Option<i64>, // 配列から移行した入札と自動入札はNone
+    pub tender_name: String,
+    pub removed_at: Option<DateTime<Utc>>, // 取り消されていなければNone
+    pub removed_by: Option<i64>,
+    pub remove_reason: Option<String>,
+    pub quantity: i32,
+    pub won_quantity: Option<i32>, // 終了時に落札できた個数
+}
+
+#[derive(Insertable, Debug)]
+#[table_name = "history_table"]
+pub struct NewTendHistory {
+    pub auction_id: i32,
+    pub tender_id: i64,
+    pub price: i64,
+    pub tended_at: DateTime<Utc>,
+    pub message_id: Option<i64>,
+    pub tender_name: String,
+    pub quantity: i32,
+}
+
+#[derive(Queryable, Debug)]
+pub struct ProxyTend {
+    pub id: i32,
+    pub auction_id: i32,
+    pub tender_id: i64,
+    pub max_price: i64,
+    pub tender_name: String,
+    pub created_at: DateTime<Utc>,
+}
+
+#[derive(Insertable, Debug)]
+#[table_name = "proxy_table"]
+pub struct NewProxyTend {
+    pub auction_id: i32,
+    pub tender_id: i64,
+    pub max_price: i64,
+    pub tender_name: String,
+    pub created_at: DateTime<Utc>,
+}
+
+#[derive(Queryable, Debug, Clone)]
+pub struct DutchSchedule {
+    pub auction_id: i32,
+    pub floor_price: i64,
+    pub step: i64,
+    pub interval_minutes: i32,
+    pub current_price: i64,
+    pub next_drop_at: DateTime<Utc>,
+}
+
+#[derive(Insertable, Debug, Clone)]
+#[table_name = "dutch_table"]
+pub struct NewDutchSchedule {
+    pub auction_id: i32,
+    pub floor_price: i64,
+    pub step: i64,
+    pub interval_minutes: i32,
+    pub current_price: i64,
+    pub next_drop_at: DateTime<Utc>,
+}
+
+fn describe_dutch(unit: &str, units: &UnitSystem, floor_price: i64, step: i64, interval_minutes: i32) -> String {
+    format!("{}分ごとに{}{}ずつ値下げ (最低価格: {}{})",
+        interval_minutes, unit, units.with_raw(step), unit, units.with_raw(floor_price))
+}
+
+impl DutchSchedule {
+    pub fn describe(&self, unit: &str, units: &UnitSystem) -> String {
+        describe_dutch(unit, units, self.floor_price, self.step, self.interval_minutes)
+    }
+}
+
+impl NewDutchSchedule {
+    pub fn describe(&self, unit: &str, units: &UnitSystem) -> String {
+        describe_dutch(unit, units, self.floor_price, self.step, self.interval_minutes)
+    }
+}
+
+#[derive(Queryable, Debug)]
+pub struct AuctionEditLog {
+    pub id: i32,
+    pub auction_id: i32,
+    pub editor_id: i64,
+    pub field: String,
+    pub old_value: String,
+    pub new_value: String,
+    pub edited_at: DateTime<Utc>,
+}
+
+#[derive(Insertable, Debug)]
+#[table_name = "edit_log_table"]
+pub struct NewAuctionEditLog {
+    pub auction_id: i32,
+    pub editor_id: i64,
+    pub field: String,
+    pub old_value: String,
+    pub new_value: String,
+    pub edited_at: DateTime<Utc>,
+}
+
+#[derive(Queryable, Debug, Clone)]
+pub struct Settlement {
+    pub id: i32,
+    pub auction_id: i32,
+    pub seller_id: i64,
+    pub winner_id: i64,
+    pub price: i64,
+    pub quantity: i32,
+    pub created_at: DateTime<Utc>,
+    pub remind_hours: i32,
+    pub delivered_at: Option<DateTime<Utc>>, // 出品者が受け渡しを確認した日時
+    pub paid_at: Option<DateTime<Utc>>, // 落札者が支払いを確認した日時
+    pub reminded_count: i32,
+    pub last_reminded_at: Option<DateTime<Utc>>,
+    pub disputed_by: Option<i64>,
+    pub dispute_reason: Option<String>,
+    pub disputed_at: Option<DateTime<Utc>>,
+    pub defaulted_at: Option<DateTime<Utc>>, // 落札者が取引を放棄した日時
+}
+
+impl Settlement {
+    pub fn is_complete(&self) -> bool {
+        self.delivered_at.is_some() && self.paid_at.is_some()
+    }
+
+    pub fn status_text(&self) -> String {
+        if self.defaulted_at.is_some() {
+            return "落札者が放棄".into();
+        }
+        if self.disputed_at.is_some() {
+            return format!("問題報告あり ({})", self.dispute_reason.as_deref().unwrap_or(""));
+        }
+        match (self.delivered_at, self.paid_at) {
+            (Some(_), Some(_)) => "完了".into(),
+            (Some(_), None) => "支払い待ち".into(),
+            (None, Some(_)) => "受け渡し待ち".into(),
+            (None, None) => "受け渡し・支払い待ち".into(),
+        }
+    }
+}
+
+#[derive(Insertable, Debug)]
+#[table_name = "settlement_table"]
+pub struct NewSettlement {
+    pub auction_id: i32,
+    pub seller_id: i64,
+    pub winner_id: i64,
+    pub price: i64,
+    pub quantity: i32,
+    pub created_at: DateTime<Utc>,
+    pub remind_hours: i32,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OfferStatus {
+    Pending,
+    Accepted,
+    Declined,
+    Expired,
+}
+
+impl OfferStatus {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Pending => "pending",
+            Self::Accepted => "accepted",
+            Self::Declined => "declined",
+            Self::Expired => "expired",
+        }
+    }
+
+    pub fn parse(text: &str) -> Option<Self> {
+        match text {
+            "pending" => Some(Self::Pending),
+            "accepted" => Some(Self::Accepted),
+            "declined" => Some(Self::Declined),
+            "expired" => Some(Self::Expired),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Queryable, Debug, Clone)]
+pub struct SecondChanceOffer {
+    pub id: i32,
+    pub settlement_id: i32, // 放棄された取引
+    pub auction_id: i32,
+    pub bidder_id: i64,
+    pub price: i64,
+    pub quantity: i32,
+    pub offered_at: DateTime<Utc>,
+    pub expires_at: DateTime<Utc>,
+    pub status: String,
+    pub responded_at: Option<DateTime<Utc>>,
+    pub accepted_settlement_id: Option<i32>,
+}
+
+impl SecondChanceOffer {
+    pub fn status(&self) -> OfferStatus {
+        OfferStatus::parse(&self.status).unwrap_or(OfferStatus::Pending)
+    }
+}
+
+#[derive(Insertable, Debug)]
+#[table_name = "offer_table"]
+pub struct NewSecondChanceOffer {
+    pub settlement_id: i32,
+    pub auction_id: i32,
+    pub bidder_id: i64,
+    pub price: i64,
+    pub quantity: i32,
+    pub offered_at: DateTime<Utc>,
+    pub expires_at: DateTime<Utc>,
+}
+
+#[derive(Queryable, Debug)]
+pub struct ChannelDeal {
+    pub channel: i64,
+    pub guild_id: Option<i64>, // 登録したサーバー
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DealStatus {
+    Open,
+    Sold,
+    Expired,
+}
+
+impl DealStatus {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Open => "open",
+            Self::Sold => "sold",
+            Self::Expired => "expired",
+        }
+    }
+
+    pub fn parse(text: &str) -> Option<Self> {
+        match text {
+            "open" => Some(Self::Open),
+            "sold" => Some(Self::Sold),
+            "expired" => Some(Self::Expired),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Queryable, Debug, Clone)]
+pub struct DealInfo {
+    pub id: i32,
+    pub channel_id: i64,
+    pub owner_id: i64,
+    pub item: String,
+    pub unit: String,
+    pub price: i64,
+    pub end_time: DateTime<Utc>,
+    pub notice: String,
+    pub embed_id: Option<i64>,
+    pub status: String,
+    pub buyer_id: Option<i64>,
+    pub finished_at: Option<DateTime<Utc>>,
+}
+
+impl DealInfo {
+    pub fn status(&self) -> DealStatus {
+        DealStatus::parse(&self.status).unwrap_or(DealStatus::Open)
+    }
+}
+
+#[derive(Insertable, Debug)]
+#[table_name = "deal_table"]
+pub struct NewDealInfo {
+    pub channel_id: i64,
+    pub owner_id: i64,
+    pub item: String,
+    pub unit: String,
+    pub price: i64,
+    pub end_time: DateTime<Utc>,
+    pub notice: String,
+}
+
+impl NewDealInfo {
+    pub fn info_embed(&self, owner: String, end_time: String, units: &UnitSystem) -> impl Fn(&mut CreateEmbed) -> &mut CreateEmbed {
+        let item = self.item.clone();
+        let price = format!("{}{}", self.unit, units.with_raw(self.price));
+        let notice = self.notice.clone();
+        move |e| {
+            e.field("出品者", &owner, true)
+             .field("出品物", &item, true)
+             .field("価格", &price, true)
+             .field("掲載期限", &end_time, true)
+             .field("特記事項", &notice, false)
+             .color(0x4259fb)
+        }
+    }
+}
+
+#[derive(Queryable, Debug, Clone)]
+pub struct UnitDefinition {
+    pub id: i32,
+    pub guild_id: i64,
+    pub currency: String, // 空文字列はサーバー全体の定義
+    pub label: String,
+    pub aliases: Vec<String>,
+    pub multiplier: i32,
+}
+
+impl UnitDefinition {
+    pub fn to_unit(&self) -> formats::Unit {
+        formats::Unit { label: self.label.clone(), aliases: self.aliases.clone(), multiplier: self.multiplier }
+    }
+}
+
+#[derive(Insertable, Debug)]
+#[table_name = "unit_table"]
+pub struct NewUnitDefinition {
+    pub guild_id: i64,
+    pub currency: String,
+    pub label: String,
+    pub aliases: Vec<String>,
+    pub multiplier: i32,
 }
@@ -4,14 +4,25 @@ table! {
         channel_id -> Int8,
         owner_id -> Int8,
         item -> Text,
-        end_time -> Timestamp,
-        start_price -> Int4,
-        bin_price -> Nullable<Int4>,
+        end_time -> Timestamptz,
+        start_price -> Int8,
+        bin_price -> Nullable<Int8>,
         unit -> Text,
         embed_id -> Nullable<Int8>,
         notice -> Text,
-        tenders_id -> Array<Int8>,
-        tends_price -> Array<Int4>,
+        min_increment -> Nullable<Int8>,
+        min_increment_percent -> Nullable<Int4>,
+        extension_window -> Nullable<Int4>,
+        extension_minutes -> Nullable<Int4>,
+        status -> Text,
+        winner_id -> Nullable<Int8>,
+        final_price -> Nullable<Int8>,
+        finished_at -> Nullable<Timestamptz>,
+        reserve_price -> Nullable<Int8>,
+        auction_type -> Text,
+        second_price -> Bool,
+        quantity -> Int4,
+        duration_minutes -> Nullable<Int4>,
     }
 }
 
@@ -19,5 +30,154 @@ table! {
     channel_auction (channel) {
         channel -> Int8,
         auction -> Nullable<Int4>,
+        extension_window -> Nullable<Int4>,
+        extension_minutes -> Nullable<Int4>,
+        settlement_remind_hours -> Int4,
+        guild_id -> Nullable<Int8>,
     }
 }
+
+table! {
+    tend_history (id) {
+        id -> Int4,
+        auction_id -> Int4,
+        tender_id -> Int8,
+        price -> Int8,
+        tended_at -> Timestamptz,
+        message_id -> Nullable<Int8>,
+        tender_name -> Text,
+        removed_at -> Nullable<Timestamptz>,
+        removed_by -> Nullable<Int8>,
+        remove_reason -> Nullable<Text>,
+        quantity -> Int4,
+        won_quantity -> Nullable<Int4>,
+    }
+}
+
+table! {
+    proxy_tend (id) {
+        id -> Int4,
+        auction_id -> Int4,
+        tender_id -> Int8,
+        max_price -> Int8,
+        tender_name -> Text,
+        created_at -> Timestamptz,
+    }
+}
+
+table! {
+    dutch_schedule (auction_id) {
+        auction_id -> Int4,
+        floor_price -> Int8,
+        step -> Int8,
+        interval_minutes -> Int4,
+        current_price -> Int8,
+        next_drop_at -> Timestamptz,
+    }
+}
+
+table! {
+    auction_edit_log (id) {
+        id -> Int4,
+        auction_id -> Int4,
+        editor_id -> Int8,
+        field -> Text,
+        old_value -> Text,
+        new_value -> Text,
+        edited_at -> Timestamptz,
+    }
+}
+
+table! {
+    settlement (id) {
+        id -> Int4,
+        auction_id -> Int4,
+        seller_id -> Int8,
+        winner_id -> Int8,
+        price -> Int8,
+        quantity -> Int4,
+        created_at -> Timestamptz,
+        remind_hours -> Int4,
+        delivered_at -> Nullable<Timestamptz>,
+        paid_at -> Nullable<Timestamptz>,
+        reminded_count -> Int4,
+        last_reminded_at -> Nullable<Timestamptz>,
+        disputed_by -> Nullable<Int8>,
+        dispute_reason -> Nullable<Text>,
+        disputed_at -> Nullable<Timestamptz>,
+        defaulted_at -> Nullable<Timestamptz>,
+    }
+}
+
+table! {
+    second_chance_offer (id) {
+        id -> Int4,
+        settlement_id -> Int4,
+        auction_id -> Int4,
+        bidder_id -> Int8,
+        price -> Int8,
+        quantity -> Int4,
+        offered_at -> Timestamptz,
+        expires_at -> Timestamptz,
+        status -> Text,
+        responded_at -> Nullable<Timestamptz>,
+        accepted_settlement_id -> Nullable<Int4>,
+    }
+}
+
+table! {
+    channel_deal (channel) {
+        channel -> Int8,
+        guild_id -> Nullable<Int8>,
+    }
+}
+
+table! {
+    deal_info (id) {
+        id -> Int4,
+        channel_id -> Int8,
+        owner_id -> Int8,
+        item -> Text,
+        unit -> Text,
+        price -> Int8,
+        end_time -> Timestamptz,
+        notice -> Text,
+        embed_id -> Nullable<Int8>,
+        status -> Text,
+        buyer_id -> Nullable<Int8>,
+        finished_at -> Nullable<Timestamptz>,
+    }
+}
+
+table! {
+    unit_definition (id) {
+        id -> Int4,
+        guild_id -> Int8,
+        currency -> Text,
+        label -> Text,
+        aliases -> Array<Text>,
+        multiplier -> Int4,
+    }
+}
+
+joinable!(tend_history -> auction_info (auction_id));
+joinable!(proxy_tend -> auction_info (auction_id));
+joinable!(dutch_schedule -> auction_info (auction_id));
+joinable!(auction_edit_log -> auction_info (auction_id));
+joinable!(settlement -> auction_info (auction_id));
+joinable!(second_chance_offer -> auction_info (auction_id));
+joinable!(deal_info -> channel_deal (channel_id));
+
+allow_tables_to_appear_in_same_query!(
+    auction_info,
+    channel_auction,
+    tend_history,
+    proxy_tend,
+    dutch_schedule,
+    auction_edit_log,
+    settlement,
+    second_chance_offer,
+    channel_deal,
+    deal_info,
+    unit_definition,
+);
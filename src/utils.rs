@@ -9,6 +9,8 @@ use serenity::async_trait;
 type PgManager = ConnectionManager<PgConnection>;
 type PgConnectionPool = Pool<PgManager>;
 type PooledPgConnection = PooledConnection<PgManager>;
+// ループや終了処理など、コマンド以外の非同期処理の結果
+pub type TaskResult = Result<(), Box<dyn std::error::Error + Send + Sync>>;
 
 struct PoolKey;
 impl TypeMapKey for PoolKey {
@@ -41,21 +43,60 @@ pub async fn insert_pool(client: &Client) {
 
 
 pub mod auction_manager {
-    use chrono::NaiveDateTime;
+    use chrono::{DateTime, Duration, Utc};
     use serenity::prelude::*;
-    use serenity::model::id::ChannelId;
+    use serenity::Result as SrnResult;
+    use serenity::model::id::{ChannelId, UserId};
+    use serenity::builder::CreateEmbed;
     use diesel::prelude::*;
-    use super::GetConnection;
+    use super::{GetConnection, TaskResult, formats, discord_helper, unit_manager};
+    use super::formats::UnitSystem;
     use crate::models::*;
     use crate::schema::{
-        channel_auction::dsl::{channel_auction, channel as channel_col, auction as auction_col},
-        auction_info::dsl::{auction_info as info_table, id as auction_id_col, tenders_id as tenders_id_col, tends_price as tends_price_col},
+        channel_auction::dsl::{channel_auction, channel as channel_col, auction as auction_col, settlement_remind_hours as settlement_remind_hours_col},
+        auction_info::dsl::{
+            auction_info as info_table, id as auction_id_col, end_time as end_time_col,
+            channel_id as info_channel_col, embed_id as embed_id_col,
+            item as item_col, notice as notice_col, bin_price as bin_price_col,
+            status as status_col, winner_id as winner_id_col, final_price as final_price_col, finished_at as finished_at_col,
+        },
+        tend_history::dsl::{
+            tend_history, id as history_id_col, auction_id as history_auction_col,
+            removed_at as removed_at_col, removed_by as removed_by_col, remove_reason as remove_reason_col,
+            won_quantity as won_quantity_col,
+        },
+        auction_edit_log::dsl::auction_edit_log,
+        dutch_schedule::dsl::{dutch_schedule, current_price as dutch_current_price_col, next_drop_at as dutch_next_drop_at_col},
+        proxy_tend::dsl::{
+            proxy_tend, id as proxy_id_col, auction_id as proxy_auction_col, tender_id as proxy_tender_col,
+            max_price as proxy_max_price_col, tender_name as proxy_tender_name_col, created_at as proxy_created_at_col,
+        },
     };
     use crate::utils::PooledPgConnection;
 
+    #[derive(Clone)]
     pub struct TendInfo {
+        pub id: i32,
         pub tender_id: u64,
-        pub price: i32,
+        pub price: i64,
+        pub tended_at: DateTime<Utc>,
+        pub message_id: Option<u64>,
+        pub tender_name: String,
+        pub quantity: i32,
+    }
+
+    impl From<TendHistory> for TendInfo {
+        fn from(history: TendHistory) -> Self {
+            Self {
+                id: history.id,
+                tender_id: history.tender_id as u64,
+                price: history.price,
+                tended_at: history.tended_at,
+                message_id: history.message_id.map(|id| id as u64),
+                tender_name: history.tender_name,
+                quantity: history.quantity,
+            }
+        }
     }
 
     #[derive(Debug)]
@@ -68,9 +109,60 @@ pub mod auction_manager {
     #[derive(Debug)]
     pub enum TendError {
         LessThanStartPrice,
-        LastTendOrLess,
+        LastTendOrLess(i64), // 比較した入札額(複数出品では本人の前回の入札額)
         SameTender,
         ByOwner,
+        Finished,
+        BelowMinIncrement,
+        DutchAuction, // ダッチオークションでの!tend
+        NotDutch, // ダッチオークション以外での!buy
+        Sealed, // 封印入札でのチャンネルでの!tend
+        NotSealed, // 封印入札以外へのDMでの入札
+        InvalidQuantity, // 出品数を超える、または複数出品でない個数指定
+        BelowWinningPrice, // 複数出品で落札圏内の最低価格に届かない
+        ProxyUnavailable, // 複数出品での自動入札
+    }
+
+    #[derive(Debug)]
+    pub enum RemoveTendError {
+        NotFound,
+        NotLastTend,
+        GracePeriodOver,
+        Finished,
+    }
+
+    // !editで変更できる項目
+    pub enum AuctionEdit {
+        Item(String),
+        Notice(String),
+        BinPrice(Option<i64>),
+        EndTime(DateTime<Utc>),
+    }
+
+    #[derive(Debug)]
+    pub enum EditError {
+        NotOwner,
+        Finished,
+        AlreadyTended, // 入札後に特記事項以外を変更しようとした
+        BinPriceUnavailable, // 即決価格を設けられない形式
+        BinPriceTooLow, // 即決価格が開始価格・最低落札価格以下
+    }
+
+    #[derive(Debug)]
+    pub enum CancelError {
+        NotOwner,
+        AlreadyTended, // 入札後の出品者による中止
+        Finished,
+    }
+
+    // 入札者が自分の入札を取り消せる期間(分)
+    pub const RETRACT_GRACE_MINUTES: i64 = 5;
+
+    #[derive(Default)]
+    pub struct TendOutcome {
+        pub finished: bool, // 即決価格以上の入札で終了した
+        pub extended: bool, // 終了間際の入札で終了日時が延長された
+        pub tends: Vec<TendInfo>, // 記録された入札(自動入札はmessage_idがNone)
     }
     
     pub struct AuctionManager {
@@ -80,11 +172,20 @@ pub mod auction_manager {
         pub item: String,
         pub unit: String,
         pub tend: Vec<TendInfo>,
-        pub end_time: NaiveDateTime,
-        pub start_price: i32,
-        pub bin_price: Option<i32>,
+        pub end_time: DateTime<Utc>,
+        pub start_price: i64,
+        pub bin_price: Option<i64>,
+        pub reserve_price: Option<i64>,
+        pub min_increment: Option<MinIncrement>,
+        pub extension: Option<EndExtension>,
         pub notice: String,
         pub embed_id: u64,
+        pub status: AuctionStatus,
+        pub auction_type: AuctionType,
+        pub dutch: Option<DutchSchedule>, // ダッチオークションのみSome
+        pub second_price: bool,
+        pub quantity: i32,
+        pub units: UnitSystem, // サーバー・通貨ごとの単位の定義
     }
 
     impl AuctionManager {
@@ -97,17 +198,16 @@ pub mod auction_manager {
                     Ok(Err(GetAuctionError::NotHeld))
                 }
             } else {
-                Ok(Err(GetAuctionError::NotHeld))
+                Ok(Err(GetAuctionError::NotAuctionChannel))
             }
         }
 
         pub fn from_id(conn: &PooledPgConnection, auction_id: i32) -> QueryResult<Result<Self, GetAuctionError>> {
             let auction_info = info_table.filter(auction_id_col.eq(auction_id)).get_result::<AuctionInfo>(conn).optional()?;
             if let Some(info) = auction_info {
-                let mut tend = vec![];
-                for (tender_id, price) in info.tenders_id.into_iter().zip(info.tends_price) {
-                    tend.push(TendInfo { tender_id: tender_id as u64, price })
-                }
+                let tend = Self::load_tend(conn, info.id)?;
+                let dutch = dutch_schedule.find(info.id).get_result::<DutchSchedule>(conn).optional()?;
+                let units = unit_manager::for_channel(conn, ChannelId(info.channel_id as u64), &info.unit)?;
                 Ok(Ok(Self {
                     channel_id: info.channel_id as u64,
                     id: info.id,
@@ -117,80 +217,1369 @@ pub mod auction_manager {
                     end_time: info.end_time,
                     start_price: info.start_price,
                     bin_price: info.bin_price,
-                    embed_id: info.embed_id.unwrap() as u64,
+                    reserve_price: info.reserve_price,
+                    min_increment: MinIncrement::from_columns(info.min_increment, info.min_increment_percent),
+                    extension: EndExtension::from_columns(info.extension_window, info.extension_minutes),
+                    embed_id: info.embed_id.map_or(0, |id| id as u64), // 順番待ちの間はembed未送信
                     unit: info.unit,
                     notice: info.notice,
+                    status: AuctionStatus::parse(&info.status).unwrap(),
+                    auction_type: AuctionType::parse(&info.auction_type).unwrap(),
+                    dutch,
+                    second_price: info.second_price,
+                    quantity: info.quantity,
+                    units,
                 }))
             } else {
                 Ok(Err(GetAuctionError::InvalidId))
             }
         }
 
-        pub fn tend(&mut self, conn: &PooledPgConnection, tender_id: u64, tend_price: i32) -> Result<bool, TendError> {
+        // 最高入札額が最低落札価格に達しているか(最低落札価格がなければ常にtrue)
+        pub fn reserve_met(&self) -> bool {
+            match (self.reserve_price, self.highest_tend()) {
+                (None, _) => true,
+                (Some(reserve_price), Some(highest_tend)) => highest_tend.price >= reserve_price,
+                (Some(_), None) => false,
+            }
+        }
+
+        // 封印入札の順位(入札額の高い順、同額なら先に入札した順)
+        pub fn ranking(&self) -> Vec<&TendInfo> {
+            let mut ranking = self.tend.iter().collect::<Vec<_>>();
+            ranking.sort_by(|a, b| b.price.cmp(&a.price).then(a.id.cmp(&b.id)));
+            ranking
+        }
+
+        // 最高額の入札。封印入札・複数出品以外では最後の入札
+        pub fn highest_tend(&self) -> Option<&TendInfo> {
+            if self.auction_type == AuctionType::Sealed || self.quantity > 1 {
+                self.ranking().first().copied()
+            } else {
+                self.tend.last()
+            }
+        }
+
+        // 最高額の入札者が支払う価格
+        // セカンドプライスの封印入札では2番目の入札額+最低入札単位(入札が1件なら開始価格)。ただし最低落札価格以上、本人の入札額以下
+        pub fn settlement_price(&self) -> Option<i64> {
+            let highest_tend = self.highest_tend()?;
+            if self.auction_type != AuctionType::Sealed || !self.second_price {
+                return Some(highest_tend.price);
+            }
+            let price = match self.ranking().get(1) {
+                Some(second) => second.price.saturating_add(self.min_increment.map_or(1, |min_increment| min_increment.step(second.price))),
+                None => self.start_price,
+            };
+            Some(price.max(self.reserve_price.unwrap_or(0)).min(highest_tend.price))
+        }
+
+        // 複数出品で、入札者ごとの最新の入札を高い順に出品数まで割り当てる(入札, 割り当てた個数)
+        // 最低落札価格は考慮しない
+        pub fn allocation(&self) -> Vec<(&TendInfo, i32)> {
+            let mut latest: Vec<&TendInfo> = Vec::new();
+            for tend in self.tend.iter().rev() {
+                if !latest.iter().any(|other| other.tender_id == tend.tender_id) {
+                    latest.push(tend);
+                }
+            }
+            latest.sort_by(|a, b| b.price.cmp(&a.price).then(a.id.cmp(&b.id)));
+
+            let mut remaining = self.quantity;
+            let mut allocation = Vec::new();
+            for tend in latest {
+                if remaining == 0 {
+                    break;
+                }
+                let quantity = tend.quantity.min(remaining);
+                remaining -= quantity;
+                allocation.push((tend, quantity));
+            }
+            allocation
+        }
+
+        // 終了時の落札者と落札個数。最低落札価格に満たない入札は落札できない
+        pub fn winners(&self) -> Vec<(&TendInfo, i32)> {
+            if self.quantity > 1 {
+                let reserve_price = self.reserve_price.unwrap_or(0);
+                self.allocation().into_iter().filter(|(tend, _)| tend.price >= reserve_price).collect()
+            } else if self.reserve_met() {
+                self.highest_tend().map(|tend| (tend, 1)).into_iter().collect()
+            } else {
+                Vec::new()
+            }
+        }
+
+        // 次の入札で必要な最低価格
+        pub fn min_next_price(&self) -> i64 {
+            if self.quantity > 1 {
+                let allocation = self.allocation();
+                return match allocation.last() {
+                    Some((lowest, _)) if allocation.iter().map(|(_, quantity)| quantity).sum::<i32>() >= self.quantity => {
+                        lowest.price.saturating_add(self.min_increment.map_or(1, |min_increment| min_increment.step(lowest.price)))
+                    },
+                    _ => self.start_price,
+                };
+            }
+            if let Some(last_tend) = self.tend.last() {
+                let step = self.min_increment.map_or(1, |min_increment| min_increment.step(last_tend.price));
+                last_tend.price.saturating_add(step)
+            } else {
+                self.start_price
+            }
+        }
+
+        fn load_tend(conn: &PooledPgConnection, auction_id: i32) -> QueryResult<Vec<TendInfo>> {
+            let history = tend_history.filter(history_auction_col.eq(auction_id)).filter(removed_at_col.is_null())
+                .order(history_id_col.asc()).load::<TendHistory>(conn)?;
+            Ok(history.into_iter().map(TendInfo::from).collect())
+        }
+
+        // auction_infoの行をロックしてから入札履歴を読み直す
+        // 同時に来た入札も1件ずつ順に検証・記録される
+        fn lock(&mut self, conn: &PooledPgConnection) -> QueryResult<()> {
+            let info = info_table.find(self.id).for_update().get_result::<AuctionInfo>(conn)?;
+            self.end_time = info.end_time;
+            self.status = AuctionStatus::parse(&info.status).unwrap();
+            self.tend = Self::load_tend(conn, self.id)?;
+            self.dutch = dutch_schedule.find(self.id).get_result::<DutchSchedule>(conn).optional()?;
+            Ok(())
+        }
 
+        pub fn tend(&mut self, conn: &PooledPgConnection, tender_id: u64, tender_name: &str, message_id: u64, tend_price: i64, tend_quantity: i32) -> QueryResult<Result<TendOutcome, TendError>> {
+            conn.transaction(|| {
+                self.lock(conn)?;
+                let mut outcome = match self.tend_locked(conn, tender_id, tender_name, message_id, tend_price, tend_quantity)? {
+                    Ok(outcome) => outcome,
+                    Err(error) => return Ok(Err(error)),
+                };
+                self.resolve_proxy(conn, &mut outcome)?;
+                Ok(Ok(outcome))
+            })
+        }
+
+        // auction_infoの行ロックを取得済みであること
+        fn tend_locked(&mut self, conn: &PooledPgConnection, tender_id: u64, tender_name: &str, message_id: u64, tend_price: i64, tend_quantity: i32) -> QueryResult<Result<TendOutcome, TendError>> {
+
+            match self.auction_type {
+                AuctionType::Dutch => return Ok(Err(TendError::DutchAuction)),
+                AuctionType::Sealed => return Ok(Err(TendError::Sealed)),
+                AuctionType::English => {},
+            }
             if tender_id == self.owner_id {
-                return Err(TendError::ByOwner);
+                return Ok(Err(TendError::ByOwner));
+            }
+            if self.is_finished() {
+                return Ok(Err(TendError::Finished));
+            }
+            if !(1 <= tend_quantity && tend_quantity <= self.quantity) {
+                return Ok(Err(TendError::InvalidQuantity));
             }
 
-            let mut finish = false;
-            if let Some(bin_price) = self.bin_price {
-                if tend_price >= bin_price {
-                    finish = true;
+            // 複数出品では入札者ごとの最新の入札が有効で、落札圏内に入る価格でなければならない
+            if self.quantity > 1 {
+                let own_price = self.tend.iter().rev().find(|tend| tend.tender_id == tender_id).map(|tend| tend.price);
+                if tend_price < self.start_price {
+                    return Ok(Err(TendError::LessThanStartPrice));
+                } else if let Some(own_price) = own_price.filter(|own_price| tend_price <= *own_price) {
+                    return Ok(Err(TendError::LastTendOrLess(own_price)));
+                } else if tend_price < self.min_next_price() {
+                    return Ok(Err(TendError::BelowWinningPrice));
                 }
+                let mut outcome = TendOutcome::default();
+                self.insert_tend(conn, tender_id, tender_name, Some(message_id), tend_price, tend_quantity, &mut outcome)?;
+                return Ok(Ok(outcome));
             }
+
+            let finish = self.bin_price.map_or(false, |bin_price| tend_price >= bin_price);
             
-            if let [.., TendInfo { tender_id: last_tender_id, price: last_tend_price }] = self.tend[..] {
-                if tender_id == last_tender_id && !finish {
-                    return Err(TendError::SameTender);
-                } else if tend_price <= last_tend_price {
-                    return Err(TendError::LastTendOrLess);
+            if let Some(last_tend) = self.tend.last() {
+                if tender_id == last_tend.tender_id && !finish {
+                    return Ok(Err(TendError::SameTender));
+                } else if tend_price <= last_tend.price {
+                    return Ok(Err(TendError::LastTendOrLess(last_tend.price)));
+                } else if tend_price < self.min_next_price() && !finish {
+                    return Ok(Err(TendError::BelowMinIncrement));
                 }
             } else {
                 if tend_price < self.start_price {
-                    return Err(TendError::LessThanStartPrice);
+                    return Ok(Err(TendError::LessThanStartPrice));
                 }
             }
-            let new_tend = TendInfo { tender_id, price: tend_price };
+
+            let mut outcome = TendOutcome::default();
+            self.insert_tend(conn, tender_id, tender_name, Some(message_id), tend_price, 1, &mut outcome)?;
+            Ok(Ok(outcome))
+        }
+
+        // 終了処理済みか、終了日時を過ぎたか、即決価格以上の入札(ダッチオークションでは購入)が既にある
+        fn is_finished(&self) -> bool {
+            self.status != AuctionStatus::Active || self.is_over()
+        }
+
+        // 終了日時を過ぎたか、即決価格以上の入札(ダッチオークションでは購入)が既にある
+        pub fn is_over(&self) -> bool {
+            if self.end_time <= Utc::now() {
+                return true;
+            }
+            if self.auction_type == AuctionType::Dutch && !self.tend.is_empty() {
+                return true;
+            }
+            self.bin_reached()
+        }
+
+        fn bin_reached(&self) -> bool {
+            match (self.bin_price, self.tend.last()) {
+                (Some(bin_price), Some(last_tend)) => last_tend.price >= bin_price,
+                _ => false,
+            }
+        }
+
+        // 終了時の状態。落札者がいなければ不成立、即決価格以上の入札で終わったなら即決
+        fn result_status(&self) -> AuctionStatus {
+            if self.winners().is_empty() {
+                AuctionStatus::Unsold
+            } else if self.bin_reached() {
+                AuctionStatus::BoughtOut
+            } else {
+                AuctionStatus::Sold
+            }
+        }
+
+        // 検証済みの入札を記録し、即決・終了日時の延長をoutcomeに反映する
+        fn insert_tend(&mut self, conn: &PooledPgConnection, tender_id: u64, tender_name: &str, message_id: Option<u64>, tend_price: i64, tend_quantity: i32, outcome: &mut TendOutcome) -> QueryResult<()> {
+            let now = Utc::now();
+            let new_tend = NewTendHistory {
+                auction_id: self.id,
+                tender_id: tender_id as i64,
+                price: tend_price,
+                tended_at: now,
+                message_id: message_id.map(|id| id as i64),
+                tender_name: tender_name.to_string(),
+                quantity: tend_quantity,
+            };
+            let new_tend: TendInfo = diesel::insert_into(tend_history).values(&new_tend).get_result::<TendHistory>(conn)?.into();
+            outcome.tends.push(new_tend.clone());
             self.tend.push(new_tend);
-            let mut tenders_id = vec![];
-            let mut tends_price = vec![];
-            for TendInfo { tender_id, price } in self.tend.iter() {
-                tenders_id.push(*tender_id as i64);
-                tends_price.push(*price);
-            }
-            diesel::update(info_table.find(self.id)).set((tenders_id_col.eq(tenders_id), tends_price_col.eq(tends_price)))
-                .execute(conn).unwrap();
-            
-            Ok(finish)
+
+            if self.bin_price.map_or(false, |bin_price| tend_price >= bin_price) {
+                outcome.finished = true;
+                return Ok(());
+            }
+
+            if let Some(extension) = self.extension {
+                let extended_end_time = now + Duration::minutes(extension.minutes as i64);
+                if self.end_time - now <= Duration::minutes(extension.window as i64) && extended_end_time > self.end_time {
+                    diesel::update(info_table.find(self.id)).set(end_time_col.eq(extended_end_time)).execute(conn)?;
+                    self.end_time = extended_end_time;
+                    outcome.extended = true;
+                }
+            }
+            Ok(())
+        }
+
+        // 自動入札の上限額を登録(既に登録済みなら更新)し、他の自動入札との競り合いを解決する
+        pub fn set_proxy(&mut self, conn: &PooledPgConnection, tender_id: u64, tender_name: &str, max_price: i64) -> QueryResult<Result<TendOutcome, TendError>> {
+            conn.transaction(|| {
+                self.lock(conn)?;
+
+                match self.auction_type {
+                    AuctionType::Dutch => return Ok(Err(TendError::DutchAuction)),
+                    AuctionType::Sealed => return Ok(Err(TendError::Sealed)),
+                    AuctionType::English => {},
+                }
+                if tender_id == self.owner_id {
+                    return Ok(Err(TendError::ByOwner));
+                }
+                if self.is_finished() {
+                    return Ok(Err(TendError::Finished));
+                }
+                if self.quantity > 1 {
+                    return Ok(Err(TendError::ProxyUnavailable));
+                }
+                if let Some(last_tend) = self.tend.last() {
+                    if max_price <= last_tend.price {
+                        return Ok(Err(TendError::LastTendOrLess(last_tend.price)));
+                    } else if max_price < self.min_next_price() && tender_id != last_tend.tender_id {
+                        // 最高額入札者は上限額を現在価格より上に設定できればよい
+                        return Ok(Err(TendError::BelowMinIncrement));
+                    }
+                } else {
+                    if max_price < self.start_price {
+                        return Ok(Err(TendError::LessThanStartPrice));
+                    }
+                }
+
+                let new_proxy = NewProxyTend {
+                    auction_id: self.id,
+                    tender_id: tender_id as i64,
+                    max_price,
+                    tender_name: tender_name.to_string(),
+                    created_at: Utc::now(),
+                };
+                diesel::insert_into(proxy_tend).values(&new_proxy)
+                    .on_conflict((proxy_auction_col, proxy_tender_col))
+                    .do_update().set((proxy_max_price_col.eq(max_price), proxy_tender_name_col.eq(tender_name)))
+                    .execute(conn)?;
+
+                let mut outcome = TendOutcome::default();
+                self.resolve_proxy(conn, &mut outcome)?;
+                Ok(Ok(outcome))
+            })
+        }
+
+        // 自動入札同士の競り合いを解決する
+        // 上限額が最も高い自動入札者(同額なら先に登録した方)が、次点の上限額を最低入札単位だけ上回る額(自分の上限額まで)で最高額入札者になる
+        // 手動の入札と上限額が同額の場合は、先に登録した自動入札が上限額で入札して最高額入札者になる
+        fn resolve_proxy(&mut self, conn: &PooledPgConnection, outcome: &mut TendOutcome) -> QueryResult<()> {
+            if outcome.finished {
+                return Ok(());
+            }
+            let leader = self.tend.last().map(|last_tend| last_tend.tender_id);
+            let proxies: Vec<ProxyTend> = proxy_tend.filter(proxy_auction_col.eq(self.id))
+                .order((proxy_max_price_col.desc(), proxy_created_at_col.asc(), proxy_id_col.asc()))
+                .load::<ProxyTend>(conn)?
+                .into_iter()
+                .filter(|proxy| self.proxy_can_respond(proxy))
+                .collect();
+
+            let top = match proxies.first() {
+                Some(top) => top,
+                None => return Ok(()),
+            };
+            // 次点は上限額まで入札する(同額の場合は先に登録した最高額の自動入札者に譲る)
+            if let Some(second) = proxies.get(1) {
+                let price = second.max_price.min(top.max_price.saturating_sub(1));
+                if Some(second.tender_id as u64) != leader && price >= self.min_next_price() {
+                    self.insert_auto_tend(conn, second, price, outcome)?;
+                    if outcome.finished {
+                        return Ok(());
+                    }
+                }
+            }
+            // 最後の1回は最低入札単位に満たなくても上限額で入札する
+            if self.tend.last().map(|last_tend| last_tend.tender_id) != Some(top.tender_id as u64) && self.proxy_can_respond(top) {
+                let price = self.min_next_price().min(top.max_price);
+                self.insert_auto_tend(conn, top, price, outcome)?;
+            }
+            Ok(())
+        }
+
+        // 自動入札が現在の最高入札に応じて入札できるか
+        // 上限額が最高入札額を上回るか、同額でその入札より先に登録したもの。最高額入札者本人の自動入札は常にtrue
+        fn proxy_can_respond(&self, proxy: &ProxyTend) -> bool {
+            match self.tend.last() {
+                None => proxy.max_price >= self.start_price,
+                Some(last_tend) => proxy.tender_id as u64 == last_tend.tender_id
+                    || proxy.max_price > last_tend.price
+                    || (proxy.max_price == last_tend.price && proxy.created_at < last_tend.tended_at),
+            }
+        }
+
+        // 封印入札への入札(DMで受け付ける)。同じ入札者の以前の入札は置き換える
+        pub fn sealed_tend(&mut self, conn: &PooledPgConnection, tender_id: u64, tender_name: &str, message_id: u64, tend_price: i64) -> QueryResult<Result<TendInfo, TendError>> {
+            conn.transaction(|| {
+                self.lock(conn)?;
+
+                if self.auction_type != AuctionType::Sealed {
+                    return Ok(Err(TendError::NotSealed));
+                }
+                if tender_id == self.owner_id {
+                    return Ok(Err(TendError::ByOwner));
+                }
+                if self.is_finished() {
+                    return Ok(Err(TendError::Finished));
+                }
+                if tend_price < self.start_price {
+                    return Ok(Err(TendError::LessThanStartPrice));
+                }
+
+                let previous = self.tend.iter().filter(|tend| tend.tender_id == tender_id).map(|tend| tend.id).collect::<Vec<_>>();
+                diesel::update(tend_history.filter(history_id_col.eq_any(previous)))
+                    .set((removed_at_col.eq(Utc::now()), removed_by_col.eq(tender_id as i64), remove_reason_col.eq("再入札による置き換え")))
+                    .execute(conn)?;
+                self.tend.retain(|tend| tend.tender_id != tender_id);
+
+                let mut outcome = TendOutcome::default();
+                self.insert_tend(conn, tender_id, tender_name, Some(message_id), tend_price, 1, &mut outcome)?;
+                Ok(Ok(outcome.tends.pop().unwrap()))
+            })
+        }
+
+        // ダッチオークションで現在価格で購入する
+        pub fn buy(&mut self, conn: &PooledPgConnection, buyer_id: u64, buyer_name: &str, message_id: u64) -> QueryResult<Result<TendInfo, TendError>> {
+            conn.transaction(|| {
+                self.lock(conn)?;
+
+                if self.auction_type != AuctionType::Dutch {
+                    return Ok(Err(TendError::NotDutch));
+                }
+                if buyer_id == self.owner_id {
+                    return Ok(Err(TendError::ByOwner));
+                }
+                if self.is_finished() {
+                    return Ok(Err(TendError::Finished));
+                }
+
+                let price = self.dutch.as_ref().unwrap().current_price;
+                let mut outcome = TendOutcome::default();
+                self.insert_tend(conn, buyer_id, buyer_name, Some(message_id), price, 1, &mut outcome)?;
+                Ok(Ok(outcome.tends.pop().unwrap()))
+            })
+        }
+
+        // ダッチオークションで値下げ時刻を過ぎていれば価格を1段階下げ、下げた後の価格を返す
+        pub fn drop_price(&mut self, conn: &PooledPgConnection) -> QueryResult<Option<i64>> {
+            conn.transaction(|| {
+                self.lock(conn)?;
+                if self.is_finished() {
+                    return Ok(None);
+                }
+                let dutch = match self.dutch.as_mut() {
+                    Some(dutch) => dutch,
+                    None => return Ok(None),
+                };
+                if dutch.next_drop_at > Utc::now() || dutch.current_price <= dutch.floor_price {
+                    return Ok(None);
+                }
+                dutch.current_price = dutch.current_price.saturating_sub(dutch.step).max(dutch.floor_price);
+                dutch.next_drop_at = dutch.next_drop_at + Duration::minutes(dutch.interval_minutes as i64);
+                diesel::update(dutch_schedule.find(self.id))
+                    .set((dutch_current_price_col.eq(dutch.current_price), dutch_next_drop_at_col.eq(dutch.next_drop_at)))
+                    .execute(conn)?;
+                Ok(Some(dutch.current_price))
+            })
+        }
+
+        // 出品者による出品内容の変更。入札後は特記事項のみ変更でき、変更は記録される
+        // (項目名, 変更前, 変更後)を返す
+        pub fn edit(&mut self, conn: &PooledPgConnection, editor_id: u64, edit: AuctionEdit) -> QueryResult<Result<(&'static str, String, String), EditError>> {
+            conn.transaction(|| {
+                self.lock(conn)?;
+
+                if editor_id != self.owner_id {
+                    return Ok(Err(EditError::NotOwner));
+                }
+                if self.is_finished() {
+                    return Ok(Err(EditError::Finished));
+                }
+                if !self.tend.is_empty() && !matches!(edit, AuctionEdit::Notice(_)) {
+                    return Ok(Err(EditError::AlreadyTended));
+                }
+
+                let (unit, units) = (self.unit.clone(), self.units.clone());
+                let describe_price = move |price: Option<i64>| price.map_or_else(|| "なし".into(), |price| format!("{}{}", unit, units.with_raw(price)));
+                let (field, old_value, new_value) = match edit {
+                    AuctionEdit::Item(item) => {
+                        diesel::update(info_table.find(self.id)).set(item_col.eq(&item)).execute(conn)?;
+                        ("出品物", std::mem::replace(&mut self.item, item.clone()), item)
+                    },
+                    AuctionEdit::Notice(notice) => {
+                        diesel::update(info_table.find(self.id)).set(notice_col.eq(&notice)).execute(conn)?;
+                        ("特記事項", std::mem::replace(&mut self.notice, notice.clone()), notice)
+                    },
+                    AuctionEdit::BinPrice(bin_price) => {
+                        if self.auction_type != AuctionType::English || self.quantity > 1 {
+                            return Ok(Err(EditError::BinPriceUnavailable));
+                        }
+                        if let Some(bin_price) = bin_price {
+                            if bin_price <= self.start_price || self.reserve_price.map_or(false, |reserve_price| bin_price < reserve_price) {
+                                return Ok(Err(EditError::BinPriceTooLow));
+                            }
+                        }
+                        diesel::update(info_table.find(self.id)).set(bin_price_col.eq(bin_price)).execute(conn)?;
+                        let old_value = describe_price(self.bin_price);
+                        self.bin_price = bin_price;
+                        ("即決価格", old_value, describe_price(bin_price))
+                    },
+                    AuctionEdit::EndTime(end_time) => {
+                        diesel::update(info_table.find(self.id)).set(end_time_col.eq(end_time)).execute(conn)?;
+                        let old_value = formats::datetime_text(&self.end_time);
+                        self.end_time = end_time;
+                        ("終了日時", old_value, formats::datetime_text(&end_time))
+                    },
+                };
+
+                let log = NewAuctionEditLog {
+                    auction_id: self.id,
+                    editor_id: editor_id as i64,
+                    field: field.to_string(),
+                    old_value: old_value.clone(),
+                    new_value: new_value.clone(),
+                    edited_at: Utc::now(),
+                };
+                diesel::insert_into(auction_edit_log).values(&log).execute(conn)?;
+                Ok(Ok((field, old_value, new_value)))
+            })
+        }
+
+        // オークションの中止。出品者は入札がない間のみ、モデレーターはいつでも中止できる
+        // 状態をcancelledにして以降の入札を受け付けないようにする。終了処理はfinishで行う
+        pub fn cancel(&mut self, conn: &PooledPgConnection, user_id: u64, is_moderator: bool) -> QueryResult<Result<(), CancelError>> {
+            conn.transaction(|| {
+                self.lock(conn)?;
+
+                if self.is_finished() {
+                    return Ok(Err(CancelError::Finished));
+                }
+                if !is_moderator {
+                    if user_id != self.owner_id {
+                        return Ok(Err(CancelError::NotOwner));
+                    }
+                    if !self.tend.is_empty() {
+                        return Ok(Err(CancelError::AlreadyTended));
+                    }
+                }
+                diesel::update(info_table.find(self.id)).set(status_col.eq(AuctionStatus::Cancelled.as_str())).execute(conn)?;
+                self.status = AuctionStatus::Cancelled;
+                Ok(Ok(()))
+            })
+        }
+
+        // 入札者による取り消し。最高額の入札で、入札からRETRACT_GRACE_MINUTES分以内のものに限る
+        pub fn retract(&mut self, conn: &PooledPgConnection, tender_id: u64) -> QueryResult<Result<(TendInfo, TendOutcome), RemoveTendError>> {
+            conn.transaction(|| {
+                self.lock(conn)?;
+                if self.is_finished() {
+                    return Ok(Err(RemoveTendError::Finished));
+                }
+                let last_tend = match self.tend.last() {
+                    Some(last_tend) if last_tend.tender_id == tender_id => last_tend,
+                    _ => return Ok(Err(RemoveTendError::NotLastTend)),
+                };
+                if Utc::now() - last_tend.tended_at > Duration::minutes(RETRACT_GRACE_MINUTES) {
+                    return Ok(Err(RemoveTendError::GracePeriodOver));
+                }
+                let tend_id = last_tend.id;
+                Ok(Ok(self.remove_locked(conn, tend_id, tender_id, "入札者による取り消し")?))
+            })
+        }
+
+        // モデレーターによる入札の削除。どの入札でも削除できる
+        pub fn remove_tend(&mut self, conn: &PooledPgConnection, tend_id: i32, moderator_id: u64, reason: &str) -> QueryResult<Result<(TendInfo, TendOutcome), RemoveTendError>> {
+            conn.transaction(|| {
+                self.lock(conn)?;
+                if self.status != AuctionStatus::Active || self.end_time <= Utc::now() {
+                    return Ok(Err(RemoveTendError::Finished));
+                }
+                if !self.tend.iter().any(|tend| tend.id == tend_id) {
+                    return Ok(Err(RemoveTendError::NotFound));
+                }
+                Ok(Ok(self.remove_locked(conn, tend_id, moderator_id, reason)?))
+            })
+        }
+
+        // 入札を取り消し扱いにし、その入札者の自動入札も取り下げる
+        // 最高額の入札が消えた場合、残った自動入札で競り合いを解決し直す
+        fn remove_locked(&mut self, conn: &PooledPgConnection, tend_id: i32, removed_by: u64, reason: &str) -> QueryResult<(TendInfo, TendOutcome)> {
+            diesel::update(tend_history.find(tend_id))
+                .set((removed_at_col.eq(Utc::now()), removed_by_col.eq(removed_by as i64), remove_reason_col.eq(reason)))
+                .execute(conn)?;
+            let index = self.tend.iter().position(|tend| tend.id == tend_id).unwrap();
+            let removed = self.tend.remove(index);
+            diesel::delete(proxy_tend.filter(proxy_auction_col.eq(self.id)).filter(proxy_tender_col.eq(removed.tender_id as i64)))
+                .execute(conn)?;
+
+            let mut outcome = TendOutcome::default();
+            self.resolve_proxy(conn, &mut outcome)?;
+            Ok((removed, outcome))
+        }
+
+        fn insert_auto_tend(&mut self, conn: &PooledPgConnection, proxy: &ProxyTend, price: i64, outcome: &mut TendOutcome) -> QueryResult<()> {
+            self.insert_tend(conn, proxy.tender_id as u64, &proxy.tender_name, None, price, 1, outcome)
+        }
+
+        fn as_new_info(&self) -> NewAuctionInfo {
+            let (min_increment, min_increment_percent) = MinIncrement::into_columns(self.min_increment);
+            let (extension_window, extension_minutes) = EndExtension::into_columns(self.extension);
+            NewAuctionInfo {
+                channel_id: self.channel_id as i64,
+                owner_id: self.owner_id as i64,
+                item: self.item.clone(),
+                end_time: self.end_time,
+                start_price: self.start_price,
+                bin_price: self.bin_price,
+                unit: self.unit.clone(),
+                notice: self.notice.clone(),
+                min_increment, min_increment_percent, extension_window, extension_minutes,
+                reserve_price: self.reserve_price,
+                auction_type: self.auction_type.as_str().to_string(),
+                second_price: self.second_price,
+                quantity: self.quantity,
+            }
+        }
+
+        async fn owner_name(&self, ctx: &Context) -> SrnResult<String> {
+            let owner = UserId(self.owner_id).to_user(ctx).await?;
+            Ok(formats::display_name(ctx, &owner, discord_helper::guild_of(ctx, ChannelId(self.channel_id)).await).await)
+        }
+
+        // 通貨と単位をつけた価格 ex. エメラルド1st+1個 (65)
+        pub fn price_text(&self, price: i64) -> String {
+            format!("{}{}", self.unit, self.units.with_raw(price))
+        }
+
+        // ピン留めするオークション内容のembed
+
+        // 開催中は現在の入札状況を、終了後は結果を表示する
+        fn embed_content(&self, owner_name: String, status: AuctionStatus) -> impl Fn(&mut CreateEmbed) -> &mut CreateEmbed {
+            // 封印入札の入札額は終了まで公開しない
+            let sealed = self.auction_type == AuctionType::Sealed && status == AuctionStatus::Active;
+            let current_price = self.tend.last().map(|last_tend| last_tend.price).filter(|_| !sealed);
+            let embed_editter = self.as_new_info().info_embed(owner_name, formats::datetime_text(&self.end_time), current_price, &self.units);
+            let id = self.id;
+            let dutch_fields = self.dutch.as_ref().filter(|_| status == AuctionStatus::Active).map(|dutch| {
+                (self.price_text(dutch.current_price), dutch.describe(&self.unit, &self.units))
+            });
+
+            let mut live_fields = Vec::new();
+            let title = if status == AuctionStatus::Active {
+                let highest = if sealed {
+                    "非公開 (終了時に公開されます)".to_string()
+                } else if self.quantity > 1 {
+                    let allocation = self.allocation();
+                    if allocation.is_empty() {
+                        "なし".into()
+                    } else {
+                        allocation.iter().map(|(tend, quantity)| format!("**{}** {} × {}個", tend.tender_name, self.price_text(tend.price), quantity))
+                            .collect::<Vec<_>>().join("\n")
+                    }
+                } else if let Some(last_tend) = self.tend.last() {
+                    format!("**{}** {}", last_tend.tender_name, self.price_text(last_tend.price))
+                } else {
+                    "なし".into()
+                };
+                let mut tenders = self.tend.iter().map(|tend| tend.tender_id).collect::<Vec<_>>();
+                tenders.sort();
+                tenders.dedup();
+                live_fields.push((if self.quantity > 1 { "落札圏内の入札" } else { "現在の最高入札" }, highest, false));
+                live_fields.push(("入札数", self.tend.len().to_string(), true));
+                live_fields.push(("入札者数", tenders.len().to_string(), true));
+                live_fields.push(("残り時間", formats::remaining_text(self.end_time - Utc::now()), true));
+                "オークション内容"
+            } else {
+                let result = match status {
+                    AuctionStatus::Sold | AuctionStatus::BoughtOut => {
+                        let winners = self.winners();
+                        if self.quantity > 1 {
+                            winners.iter().map(|(tend, quantity)| format!("**{}** {} × {}個", tend.tender_name, self.price_text(tend.price), quantity))
+                                .collect::<Vec<_>>().join("\n")
+                        } else {
+                            let winner = winners.first().map_or_else(String::new, |(tend, _)| tend.tender_name.clone());
+                            let price = self.settlement_price().map_or_else(String::new, |price| self.price_text(price));
+                            format!("落札者: **{}**\n落札額: **{}**", winner, price)
+                        }
+                    },
+                    AuctionStatus::Cancelled => "中止".into(),
+                    _ => "不成立".into(),
+                };
+                live_fields.push(("結果", result, false));
+                "オークション内容 (終了)"
+            };
+
+            move |e| {
+                e.title(title).field("ID", id, false);
+                embed_editter(e);
+                if let Some((current_price, schedule)) = &dutch_fields {
+                    e.field("現在価格", current_price, false).field("値下げ", schedule, false);
+                }
+                for (name, value, inline) in &live_fields {
+                    e.field(name, value, *inline);
+                }
+                if title != "オークション内容" {
+                    e.color(0x808080);
+                }
+                e
+            }
+        }
+
+        async fn render_embed(&self, ctx: &Context, status: AuctionStatus) -> SrnResult<()> {
+            let embed_content = self.embed_content(self.owner_name(ctx).await?, status);
+            ChannelId(self.channel_id).edit_message(ctx, self.embed_id, |m| {
+                m.embed(|e| embed_content(e))
+            }).await?;
+            Ok(())
+        }
+
+        // ピン留めされたオークション内容のembedを現在の情報で書き直す
+        pub async fn update_embed(&self, ctx: &Context) -> SrnResult<()> {
+            self.render_embed(ctx, self.status).await
+        }
+
+        // チャンネルで順番待ちしているオークションの数
+        pub fn queue_length(conn: &PooledPgConnection, channel_id: ChannelId) -> QueryResult<i64> {
+            info_table.filter(info_channel_col.eq(channel_id.0 as i64)).filter(status_col.eq(AuctionStatus::Queued.as_str()))
+                .count().get_result(conn)
+        }
+
+        // 順番待ちの先頭のオークションを開催中にし、そのIDを返す。終了日時は開始時刻に出品時の開催期間を足したもの
+        // チャンネルの行をロックし、開催中のオークションがない場合のみ開始するので、同時に呼ばれても1件しか開始しない
+        fn activate_queued(conn: &PooledPgConnection, channel_id: ChannelId) -> QueryResult<Option<i32>> {
+            conn.transaction(|| {
+                let current = channel_auction.find(channel_id.0 as i64).select(auction_col).for_update()
+                    .get_result::<Option<i32>>(conn).optional()?;
+                if current != Some(None) {
+                    return Ok(None);
+                }
+                let next = info_table.filter(info_channel_col.eq(channel_id.0 as i64)).filter(status_col.eq(AuctionStatus::Queued.as_str()))
+                    .order(auction_id_col.asc()).first::<AuctionInfo>(conn).optional()?;
+                let next = match next {
+                    Some(next) => next,
+                    None => return Ok(None),
+                };
+
+                let now = Utc::now();
+                let end_time = now + Duration::minutes(next.duration_minutes.unwrap_or(0) as i64);
+                diesel::update(info_table.find(next.id))
+                    .set((status_col.eq(AuctionStatus::Active.as_str()), end_time_col.eq(end_time)))
+                    .execute(conn)?;
+                if let Some(dutch) = dutch_schedule.find(next.id).get_result::<DutchSchedule>(conn).optional()? {
+                    diesel::update(dutch_schedule.find(next.id))
+                        .set(dutch_next_drop_at_col.eq(now + Duration::minutes(dutch.interval_minutes as i64)))
+                        .execute(conn)?;
+                }
+                diesel::update(channel_auction.find(channel_id.0 as i64)).set(auction_col.eq(next.id)).execute(conn)?;
+                Ok(Some(next.id))
+            })
+        }
+
+        // 順番待ちの先頭のオークションを開始して告知する
+        pub async fn start_queued(ctx: &Context, channel_id: ChannelId) -> TaskResult {
+            let conn = ctx.pool().await.get()?;
+            let next_id = match Self::activate_queued(&conn, channel_id)? {
+                Some(next_id) => next_id,
+                None => return Ok(()),
+            };
+
+            let manager = match Self::from_id(&conn, next_id)? {
+                Ok(manager) => manager,
+                Err(why) => return Err(format!("{:?}", why).into()),
+            };
+            let embed_content = manager.embed_content(manager.owner_name(ctx).await?, manager.status);
+            let embed_message = channel_id.send_message(ctx, |m| {
+                m.content(format!("<@{}> 順番待ちしていたオークションを開始します", manager.owner_id))
+                 .embed(|e| embed_content(e))
+            }).await?;
+            embed_message.pin(ctx).await?;
+            diesel::update(info_table.find(next_id)).set(embed_id_col.eq(Some(embed_message.id.0 as i64))).execute(&conn)?;
+            Ok(())
         }
         
-        pub async fn finish(&self, ctx: &Context) {
-            let conn = ctx.get_connection().await;
-            diesel::update(channel_auction).filter(auction_col.eq(Some(self.id))).set(auction_col.eq(None::<i32>)).execute(&conn).unwrap();
-            ChannelId(self.channel_id).unpin(ctx, self.embed_id).await.unwrap();
+        // 結果を記録してチャンネルを空ける。落札者が決まる状態なら落札した入札に落札個数を記録し、落札者ごとに取引を記録する
+        // winner_id・final_priceには最高額の落札者を記録する
+        // オークションの行をロックし、終了処理がまだの場合のみ1つのトランザクションで記録して(記録した状態, 取引)を返す
+        // 状態は、中止済みなら中止、開催中で終了日時を過ぎたか即決・購入があれば結果に応じたもの。それ以外はNone
+        // 即決と定期処理が重なったり、途中で失敗して再度呼ばれたりしても、取引が二重に記録されることはない
+        pub fn finish(&mut self, conn: &PooledPgConnection) -> QueryResult<Option<(AuctionStatus, Vec<Settlement>)>> {
+            conn.transaction(|| {
+                self.lock(conn)?;
+                let status = match self.status {
+                    AuctionStatus::Cancelled => AuctionStatus::Cancelled,
+                    AuctionStatus::Active if self.is_over() => self.result_status(),
+                    _ => return Ok(None),
+                };
+                let winners = if status.has_winner() { self.winners() } else { Vec::new() };
+                let winner = winners.first().map(|(tend, _)| *tend);
+                let updated = diesel::update(info_table.find(self.id)
+                    .filter(status_col.eq(self.status.as_str()))
+                    .filter(finished_at_col.is_null()))
+                    .set((
+                        status_col.eq(status.as_str()),
+                        winner_id_col.eq(winner.map(|tend| tend.tender_id as i64)),
+                        final_price_col.eq(winner.and(self.settlement_price())),
+                        finished_at_col.eq(Some(Utc::now())),
+                    )).execute(conn)?;
+                if updated == 0 {
+                    return Ok(None);
+                }
+                for (tend, quantity) in &winners {
+                    diesel::update(tend_history.find(tend.id)).set(won_quantity_col.eq(Some(*quantity))).execute(conn)?;
+                }
+
+                let settlements = if winners.is_empty() {
+                    Vec::new()
+                } else {
+                    let remind_hours = channel_auction.find(self.channel_id as i64).select(settlement_remind_hours_col).get_result::<i32>(conn)?;
+                    let now = Utc::now();
+                    let new_settlements = winners.iter().map(|(tend, quantity)| NewSettlement {
+                        auction_id: self.id,
+                        seller_id: self.owner_id as i64,
+                        winner_id: tend.tender_id as i64,
+                        price: if self.quantity > 1 { tend.price } else { self.settlement_price().unwrap_or(tend.price) },
+                        quantity: *quantity,
+                        created_at: now,
+                        remind_hours,
+                    }).collect::<Vec<_>>();
+                    super::settlement_manager::create(conn, &new_settlements)?
+                };
+
+                diesel::update(channel_auction).filter(auction_col.eq(Some(self.id))).set(auction_col.eq(None::<i32>)).execute(conn)?;
+                self.status = status;
+                Ok(Some((status, settlements)))
+            })
+        }
+
+        // finishで記録した後の通知。出品者と落札者に取引の確認方法を知らせ、結果を表示して次のオークションを開始する
+        pub async fn after_finish(&self, ctx: &Context, status: AuctionStatus, settlements: &[Settlement]) -> TaskResult {
+            for settlement in settlements {
+                let content = format!("{}(オークションID: {})の取引ID: **{}**\n\
+                    出品者は受け渡し後に`!delivered {2}`、落札者は支払い後に`!paid {2}`を送ってください。\n\
+                    問題があれば`!dispute {2} 理由`で報告できます。", self.item, self.id, settlement.id);
+                for user_id in &[settlement.seller_id, settlement.winner_id] {
+                    if let Ok(user) = UserId(*user_id as u64).to_user(ctx).await {
+                        let _ = user.direct_message(ctx, |m| m.content(&content)).await;
+                    }
+                }
+            }
+
+            // 結果を表示してからピン留めを外す。メッセージが削除されていても順番待ちのオークションは開始する
+            let _ = self.render_embed(ctx, status).await;
+            if let Err(why) = ChannelId(self.channel_id).unpin(ctx, self.embed_id).await {
+                println!("Error unpinning auction {}: {:?}", self.id, why);
+            }
+            Self::start_queued(ctx, ChannelId(self.channel_id)).await
+        }
+    }
+}
+pub use auction_manager::{AuctionManager, GetAuctionError, TendError, TendOutcome, TendInfo, RemoveTendError, RETRACT_GRACE_MINUTES, AuctionEdit, EditError, CancelError};
+
+
+pub mod settlement_manager {
+    use chrono::{Duration, Utc};
+    use diesel::prelude::*;
+    use serenity::prelude::*;
+    use serenity::model::id::UserId;
+    use crate::models::*;
+    use crate::schema::{
+        auction_info::dsl::{auction_info, id as info_id_col, item, winner_id as info_winner_col, final_price as info_final_price_col},
+        settlement::dsl::{
+            settlement, id as settlement_id_col, auction_id as settlement_auction_col, winner_id as settlement_winner_col,
+            delivered_at, paid_at, reminded_count, last_reminded_at, disputed_by, dispute_reason, disputed_at, defaulted_at,
+        },
+        settlement::all_columns as settlement_columns,
+        tend_history::dsl::{
+            tend_history, id as history_id_col, auction_id as history_auction_col, tender_id, price as history_price_col, removed_at,
+        },
+        second_chance_offer::dsl::{
+            second_chance_offer, auction_id as offer_auction_col, bidder_id, expires_at, status as offer_status_col,
+            responded_at, accepted_settlement_id,
+        },
+    };
+    use crate::utils::PooledPgConnection;
+    use crate::utils::formats::UnitSystem;
+
+    // 1件の取引に送るリマインドの上限。以降はモデレーターが!unsettledで確認する
+    pub const MAX_REMINDERS: i32 = 3;
+    // 次点の入札者が提示に返答できる時間
+    pub const SECOND_CHANCE_HOURS: i64 = 24;
+
+    #[derive(Debug)]
+    pub enum SettlementError {
+        NotFound,
+        NotParty, // 出品者・落札者以外
+        AlreadyConfirmed,
+        Disputed,
+        Defaulted, // 落札者が放棄した取引
+    }
+
+    #[derive(Debug)]
+    pub enum DefaultError {
+        NotFound,
+        NotSeller, // 出品者・モデレーター以外
+        AlreadyPaid,
+        AlreadyDefaulted,
+    }
+
+    #[derive(Debug)]
+    pub enum OfferError {
+        NotFound,
+        NotBidder, // 提示された入札者以外
+        AlreadyResponded,
+        Expired,
+    }
+
+    #[derive(Debug, Clone, Copy)]
+    pub enum Confirmation {
+        Delivered, // 出品者による受け渡しの確認
+        Paid, // 落札者による支払いの確認
+    }
+
+    // 落札者ごとに取引を記録する
+    pub fn create(conn: &PooledPgConnection, new_settlements: &[NewSettlement]) -> QueryResult<Vec<Settlement>> {
+        diesel::insert_into(settlement).values(new_settlements).get_results(conn)
+    }
+
+    pub fn confirm(conn: &PooledPgConnection, settlement_id: i32, user_id: u64, confirmation: Confirmation) -> QueryResult<Result<Settlement, SettlementError>> {
+        conn.transaction(|| {
+            let target = match settlement.find(settlement_id).for_update().get_result::<Settlement>(conn).optional()? {
+                Some(target) => target,
+                None => return Ok(Err(SettlementError::NotFound)),
+            };
+            let (party_id, confirmed_at) = match confirmation {
+                Confirmation::Delivered => (target.seller_id, target.delivered_at),
+                Confirmation::Paid => (target.winner_id, target.paid_at),
+            };
+            if party_id != user_id as i64 {
+                return Ok(Err(SettlementError::NotParty));
+            }
+            if target.defaulted_at.is_some() {
+                return Ok(Err(SettlementError::Defaulted));
+            }
+            if target.disputed_at.is_some() {
+                return Ok(Err(SettlementError::Disputed));
+            }
+            if confirmed_at.is_some() {
+                return Ok(Err(SettlementError::AlreadyConfirmed));
+            }
+
+            let now = Some(Utc::now());
+            let updated: Settlement = match confirmation {
+                Confirmation::Delivered => diesel::update(settlement.find(settlement_id)).set(delivered_at.eq(now)).get_result(conn)?,
+                Confirmation::Paid => diesel::update(settlement.find(settlement_id)).set(paid_at.eq(now)).get_result(conn)?,
+            };
+            Ok(Ok(updated))
+        })
+    }
+
+    // 出品者か落札者が取引の問題を報告する。報告後はリマインドを送らない
+    pub fn dispute(conn: &PooledPgConnection, settlement_id: i32, user_id: u64, reason: &str) -> QueryResult<Result<Settlement, SettlementError>> {
+        conn.transaction(|| {
+            let target = match settlement.find(settlement_id).for_update().get_result::<Settlement>(conn).optional()? {
+                Some(target) => target,
+                None => return Ok(Err(SettlementError::NotFound)),
+            };
+            if target.seller_id != user_id as i64 && target.winner_id != user_id as i64 {
+                return Ok(Err(SettlementError::NotParty));
+            }
+            if target.defaulted_at.is_some() {
+                return Ok(Err(SettlementError::Defaulted));
+            }
+            if target.disputed_at.is_some() {
+                return Ok(Err(SettlementError::Disputed));
+            }
+            let updated: Settlement = diesel::update(settlement.find(settlement_id))
+                .set((disputed_by.eq(Some(user_id as i64)), dispute_reason.eq(Some(reason)), disputed_at.eq(Some(Utc::now()))))
+                .get_result(conn)?;
+            Ok(Ok(updated))
+        })
+    }
+
+    // 未完了の取引と出品物
+    fn load_open(conn: &PooledPgConnection) -> QueryResult<Vec<(Settlement, String)>> {
+        settlement.inner_join(auction_info)
+            .filter(delivered_at.is_null().or(paid_at.is_null()))
+            .filter(defaulted_at.is_null())
+            .order(settlement_id_col.asc())
+            .select((settlement_columns, item))
+            .load(conn)
+    }
+
+    // リマインドを送る取引。最後のリマインド(なければ取引の作成)からremind_hours経過したもの
+    pub fn due_reminders(conn: &PooledPgConnection) -> QueryResult<Vec<(Settlement, String)>> {
+        let now = Utc::now();
+        Ok(load_open(conn)?.into_iter().filter(|(target, _)| {
+            target.disputed_at.is_none() && target.reminded_count < MAX_REMINDERS
+                && target.last_reminded_at.unwrap_or(target.created_at) + Duration::hours(target.remind_hours as i64) <= now
+        }).collect())
+    }
+
+    pub fn mark_reminded(conn: &PooledPgConnection, settlement_id: i32) -> QueryResult<()> {
+        diesel::update(settlement.find(settlement_id))
+            .set((reminded_count.eq(reminded_count + 1), last_reminded_at.eq(Some(Utc::now()))))
+            .execute(conn)?;
+        Ok(())
+    }
+
+    // 期限切れの取引。作成からremind_hours以上経っても完了していないもの、または問題が報告されたもの
+    pub fn overdue(conn: &PooledPgConnection) -> QueryResult<Vec<(Settlement, String)>> {
+        let now = Utc::now();
+        Ok(load_open(conn)?.into_iter().filter(|(target, _)| {
+            target.disputed_at.is_some() || target.created_at + Duration::hours(target.remind_hours as i64) <= now
+        }).collect())
+    }
+
+    // 落札者が取引を放棄したことを記録する。支払い済みの取引は放棄にできない
+    // オークションのwinner_id・final_priceは、次点の入札者が承諾するまで放棄した落札者のままにする
+    pub fn mark_defaulted(conn: &PooledPgConnection, settlement_id: i32, user_id: u64, is_moderator: bool) -> QueryResult<Result<Settlement, DefaultError>> {
+        conn.transaction(|| {
+            let target = match settlement.find(settlement_id).for_update().get_result::<Settlement>(conn).optional()? {
+                Some(target) => target,
+                None => return Ok(Err(DefaultError::NotFound)),
+            };
+            if !is_moderator && target.seller_id != user_id as i64 {
+                return Ok(Err(DefaultError::NotSeller));
+            }
+            if target.defaulted_at.is_some() {
+                return Ok(Err(DefaultError::AlreadyDefaulted));
+            }
+            if target.paid_at.is_some() {
+                return Ok(Err(DefaultError::AlreadyPaid));
+            }
+            let updated: Settlement = diesel::update(settlement.find(settlement_id))
+                .set(defaulted_at.eq(Some(Utc::now())))
+                .get_result(conn)?;
+            Ok(Ok(updated))
+        })
+    }
+
+    // 放棄された取引の出品物を次点の入札者に提示する
+    // 落札者と提示済みの入札者を除き、最も高い入札をした入札者にその入札額で提示する。候補がいなければNone
+    pub fn offer_next(conn: &PooledPgConnection, source: &Settlement) -> QueryResult<Option<SecondChanceOffer>> {
+        conn.transaction(|| {
+            let mut excluded = settlement.filter(settlement_auction_col.eq(source.auction_id))
+                .select(settlement_winner_col).load::<i64>(conn)?;
+            excluded.extend(second_chance_offer.filter(offer_auction_col.eq(source.auction_id))
+                .select(bidder_id).load::<i64>(conn)?);
+            excluded.push(source.seller_id);
+
+            let candidate = tend_history
+                .filter(history_auction_col.eq(source.auction_id))
+                .filter(removed_at.is_null())
+                .filter(tender_id.ne_all(excluded))
+                .order((history_price_col.desc(), history_id_col.asc()))
+                .first::<TendHistory>(conn).optional()?;
+            let candidate = match candidate {
+                Some(candidate) => candidate,
+                None => return Ok(None),
+            };
+
+            let now = Utc::now();
+            let offer = NewSecondChanceOffer {
+                settlement_id: source.id,
+                auction_id: source.auction_id,
+                bidder_id: candidate.tender_id,
+                price: candidate.price,
+                quantity: candidate.quantity.min(source.quantity),
+                offered_at: now,
+                expires_at: now + Duration::hours(SECOND_CHANCE_HOURS),
+            };
+            diesel::insert_into(second_chance_offer).values(&offer).get_result::<SecondChanceOffer>(conn).map(Some)
+        })
+    }
+
+    fn load_offer(conn: &PooledPgConnection, offer_id: i32, user_id: u64) -> QueryResult<Result<SecondChanceOffer, OfferError>> {
+        let offer = match second_chance_offer.find(offer_id).for_update().get_result::<SecondChanceOffer>(conn).optional()? {
+            Some(offer) => offer,
+            None => return Ok(Err(OfferError::NotFound)),
+        };
+        if offer.bidder_id != user_id as i64 {
+            return Ok(Err(OfferError::NotBidder));
+        }
+        if offer.status() != OfferStatus::Pending {
+            return Ok(Err(OfferError::AlreadyResponded));
+        }
+        if offer.expires_at <= Utc::now() {
+            return Ok(Err(OfferError::Expired));
         }
+        Ok(Ok(offer))
+    }
+
+    // 提示を承諾し、次点の入札者との取引を記録する
+    // 放棄されたのが最高額の落札者の取引なら、オークションのwinner_id・final_priceも承諾した入札者にする
+    pub fn accept(conn: &PooledPgConnection, offer_id: i32, user_id: u64) -> QueryResult<Result<(SecondChanceOffer, Settlement), OfferError>> {
+        conn.transaction(|| {
+            let offer = match load_offer(conn, offer_id, user_id)? {
+                Ok(offer) => offer,
+                Err(error) => return Ok(Err(error)),
+            };
+            let source = settlement.find(offer.settlement_id).get_result::<Settlement>(conn)?;
+            let now = Utc::now();
+            let created = create(conn, &[NewSettlement {
+                auction_id: offer.auction_id,
+                seller_id: source.seller_id,
+                winner_id: offer.bidder_id,
+                price: offer.price,
+                quantity: offer.quantity,
+                created_at: now,
+                remind_hours: source.remind_hours,
+            }])?.remove(0);
+            let offer = diesel::update(second_chance_offer.find(offer_id))
+                .set((offer_status_col.eq(OfferStatus::Accepted.as_str()), responded_at.eq(Some(now)), accepted_settlement_id.eq(Some(created.id))))
+                .get_result::<SecondChanceOffer>(conn)?;
+            diesel::update(auction_info.filter(info_id_col.eq(offer.auction_id)).filter(info_winner_col.eq(Some(source.winner_id))))
+                .set((info_winner_col.eq(Some(offer.bidder_id)), info_final_price_col.eq(Some(offer.price))))
+                .execute(conn)?;
+            Ok(Ok((offer, created)))
+        })
+    }
+
+    // 提示を辞退する。呼び出し側で次の入札者に提示する
+    pub fn decline(conn: &PooledPgConnection, offer_id: i32, user_id: u64) -> QueryResult<Result<SecondChanceOffer, OfferError>> {
+        conn.transaction(|| {
+            if let Err(error) = load_offer(conn, offer_id, user_id)? {
+                return Ok(Err(error));
+            }
+            let offer = diesel::update(second_chance_offer.find(offer_id))
+                .set((offer_status_col.eq(OfferStatus::Declined.as_str()), responded_at.eq(Some(Utc::now()))))
+                .get_result::<SecondChanceOffer>(conn)?;
+            Ok(Ok(offer))
+        })
+    }
+
+    // 返答期限を過ぎた提示を期限切れにする
+    pub fn expire_offers(conn: &PooledPgConnection) -> QueryResult<Vec<SecondChanceOffer>> {
+        diesel::update(second_chance_offer
+            .filter(offer_status_col.eq(OfferStatus::Pending.as_str()))
+            .filter(expires_at.le(Utc::now())))
+            .set(offer_status_col.eq(OfferStatus::Expired.as_str()))
+            .get_results(conn)
+    }
+
+    // 提示のもとになった放棄された取引とオークション
+    pub fn source_of(conn: &PooledPgConnection, offer: &SecondChanceOffer) -> QueryResult<(Settlement, AuctionInfo)> {
+        settlement.inner_join(auction_info)
+            .filter(settlement_id_col.eq(offer.settlement_id))
+            .get_result(conn)
+    }
+
+    // 次点の入札者に提示を知らせる。候補がいなければ出品者に知らせる
+    pub async fn announce_offer(ctx: &Context, info: &AuctionInfo, units: &UnitSystem, source: &Settlement, offer: Option<&SecondChanceOffer>) {
+        let (user_id, content) = match offer {
+            Some(offer) => (offer.bidder_id, format!("{item}(オークションID: {auction})の落札者が取引を放棄したため、あなたの入札額{unit}{price}で{quantity}個を購入できます。\n\
+                購入する場合は`!accept {id}`、辞退する場合は`!decline {id}`を{hours}時間以内に送ってください。(提示ID: {id})",
+                item = info.item, auction = info.id, unit = info.unit, price = units.with_raw(offer.price),
+                quantity = offer.quantity, id = offer.id, hours = SECOND_CHANCE_HOURS)),
+            None => (source.seller_id, format!("{}(オークションID: {})の取引ID {} を提示できる次点の入札者はいません", info.item, info.id, source.id)),
+        };
+        if let Ok(user) = UserId(user_id as u64).to_user(ctx).await {
+            let _ = user.direct_message(ctx, |m| m.content(content)).await;
+        }
+    }
+}
+pub use settlement_manager::{SettlementError, Confirmation, DefaultError, OfferError};
+
+
+pub mod deal_manager {
+    use chrono::Utc;
+    use diesel::prelude::*;
+    use serenity::prelude::*;
+    use serenity::model::id::{ChannelId, MessageId};
+    use crate::models::*;
+    use crate::schema::{
+        channel_deal::dsl::channel_deal,
+        deal_info::dsl::{deal_info, id as deal_id_col, channel_id as deal_channel_col, status, end_time, buyer_id, finished_at},
+    };
+    use crate::utils::PooledPgConnection;
+
+    #[derive(Debug)]
+    pub enum BuyError {
+        NotFound,
+        Ambiguous(Vec<i32>), // 出品IDが省略されたが、掲載中の出品が複数ある
+        ByOwner,
+        Closed, // 購入済み・期限切れ
+    }
+
+    pub fn is_deal_channel(conn: &PooledPgConnection, channel: ChannelId) -> QueryResult<bool> {
+        channel_deal.find(channel.0 as i64).first::<ChannelDeal>(conn).optional().map(|result| result.is_some())
+    }
+
+    // チャンネルに掲載中の出品
+    pub fn open_deals(conn: &PooledPgConnection, channel: ChannelId) -> QueryResult<Vec<DealInfo>> {
+        deal_info.filter(deal_channel_col.eq(channel.0 as i64))
+            .filter(status.eq(DealStatus::Open.as_str()))
+            .order(deal_id_col.asc())
+            .load(conn)
+    }
+
+    // 出品を購入する。出品IDを省略した場合、掲載中の出品が1件だけならそれを購入する
+    pub fn buy(conn: &PooledPgConnection, channel: ChannelId, deal_id: Option<i32>, buyer: u64) -> QueryResult<Result<DealInfo, BuyError>> {
+        conn.transaction(|| {
+            let target = match deal_id {
+                Some(deal_id) => deal_info.filter(deal_channel_col.eq(channel.0 as i64)).filter(deal_id_col.eq(deal_id))
+                    .for_update().get_result::<DealInfo>(conn).optional()?,
+                None => {
+                    let mut open = deal_info.filter(deal_channel_col.eq(channel.0 as i64))
+                        .filter(status.eq(DealStatus::Open.as_str()))
+                        .order(deal_id_col.asc())
+                        .for_update().load::<DealInfo>(conn)?;
+                    if open.len() > 1 {
+                        return Ok(Err(BuyError::Ambiguous(open.iter().map(|deal| deal.id).collect())));
+                    }
+                    open.pop()
+                }
+            };
+            let target = match target {
+                Some(target) => target,
+                None => return Ok(Err(BuyError::NotFound)),
+            };
+            let now = Utc::now();
+            if target.status() != DealStatus::Open || target.end_time <= now {
+                return Ok(Err(BuyError::Closed));
+            }
+            if target.owner_id == buyer as i64 {
+                return Ok(Err(BuyError::ByOwner));
+            }
+            let updated = diesel::update(deal_info.find(target.id))
+                .set((status.eq(DealStatus::Sold.as_str()), buyer_id.eq(Some(buyer as i64)), finished_at.eq(Some(now))))
+                .get_result::<DealInfo>(conn)?;
+            Ok(Ok(updated))
+        })
+    }
+
+    // 掲載期限を過ぎた出品を期限切れにする
+    pub fn expire(conn: &PooledPgConnection) -> QueryResult<Vec<DealInfo>> {
+        let now = Utc::now();
+        diesel::update(deal_info.filter(status.eq(DealStatus::Open.as_str())).filter(end_time.le(now)))
+            .set((status.eq(DealStatus::Expired.as_str()), finished_at.eq(Some(now))))
+            .get_results(conn)
+    }
+
+    // 掲載を終えた出品のembedに結果を表示する
+    pub async fn close_embed(ctx: &Context, deal: &DealInfo) -> serenity::Result<()> {
+        let embed_id = match deal.embed_id {
+            Some(embed_id) => embed_id,
+            None => return Ok(()),
+        };
+        let content = match (deal.status(), deal.buyer_id) {
+            (DealStatus::Sold, Some(buyer)) => format!("【購入済み】購入者: <@{}>", buyer),
+            (DealStatus::Expired, _) => "【期限切れ】".into(),
+            _ => return Ok(()),
+        };
+        ChannelId(deal.channel_id as u64).edit_message(ctx, MessageId(embed_id as u64), |m| m.content(content)).await?;
+        Ok(())
+    }
+}
+pub use deal_manager::BuyError;
+
+
+pub mod unit_manager {
+    use diesel::prelude::*;
+    use serenity::model::id::{ChannelId, GuildId};
+    use crate::models::*;
+    use crate::schema::{
+        channel_auction::dsl::{channel_auction, channel as auction_channel_col, guild_id as auction_guild_col},
+        channel_deal::dsl::{channel_deal, channel as deal_channel_col, guild_id as deal_guild_col},
+        unit_definition::dsl::{unit_definition, guild_id, currency as currency_col, label as label_col, aliases as aliases_col, multiplier as multiplier_col},
+    };
+    use crate::utils::PooledPgConnection;
+    use crate::utils::formats::UnitSystem;
+
+    // サーバーの単位の定義。通貨ごとの定義をサーバー全体の定義より優先する
+    pub fn for_guild(conn: &PooledPgConnection, guild: Option<GuildId>, currency: &str) -> QueryResult<UnitSystem> {
+        let mut units = UnitSystem::default();
+        if let Some(GuildId(guild)) = guild {
+            let definitions = unit_definition.filter(guild_id.eq(guild as i64))
+                .filter(currency_col.eq("").or(currency_col.eq(currency)))
+                .load::<UnitDefinition>(conn)?;
+            units.overlay(definitions.iter().filter(|definition| definition.currency.is_empty()).map(UnitDefinition::to_unit));
+            units.overlay(definitions.iter().filter(|definition| !definition.currency.is_empty()).map(UnitDefinition::to_unit));
+        }
+        Ok(units)
+    }
+
+    // オークション・取引チャンネルの登録時に記録したサーバーの単位の定義。記録がなければ既定の単位
+    // メッセージのないループ処理などで使う。コマンドではfor_guildと同じサーバーの定義になる
+    pub fn for_channel(conn: &PooledPgConnection, channel: ChannelId, currency: &str) -> QueryResult<UnitSystem> {
+        for_guild(conn, guild_of(conn, channel)?, currency)
+    }
+
+    pub fn guild_of(conn: &PooledPgConnection, ChannelId(channel): ChannelId) -> QueryResult<Option<GuildId>> {
+        let auction_guild = channel_auction.find(channel as i64).select(auction_guild_col).get_result::<Option<i64>>(conn).optional()?.flatten();
+        let guild = match auction_guild {
+            Some(guild) => Some(guild),
+            None => channel_deal.find(channel as i64).select(deal_guild_col).get_result::<Option<i64>>(conn).optional()?.flatten(),
+        };
+        Ok(guild.map(|guild| GuildId(guild as u64)))
+    }
+
+    // サーバーが記録されていないオークション・取引チャンネルに、そのチャンネルのサーバーを記録する
+    pub fn record_guild(conn: &PooledPgConnection, GuildId(guild): GuildId, channels: &[i64]) -> QueryResult<usize> {
+        let auctions = diesel::update(channel_auction.filter(auction_channel_col.eq_any(channels)).filter(auction_guild_col.is_null()))
+            .set(auction_guild_col.eq(guild as i64))
+            .execute(conn)?;
+        let deals = diesel::update(channel_deal.filter(deal_channel_col.eq_any(channels)).filter(deal_guild_col.is_null()))
+            .set(deal_guild_col.eq(guild as i64))
+            .execute(conn)?;
+        Ok(auctions + deals)
+    }
+
+    pub fn list(conn: &PooledPgConnection, GuildId(guild): GuildId) -> QueryResult<Vec<UnitDefinition>> {
+        unit_definition.filter(guild_id.eq(guild as i64)).order((currency_col.asc(), multiplier_col.desc())).load(conn)
+    }
+
+    // 同じ通貨・表示名の定義があれば置き換える
+    pub fn set(conn: &PooledPgConnection, definition: &NewUnitDefinition) -> QueryResult<UnitDefinition> {
+        diesel::insert_into(unit_definition).values(definition)
+            .on_conflict((guild_id, currency_col, label_col))
+            .do_update()
+            .set((aliases_col.eq(&definition.aliases), multiplier_col.eq(definition.multiplier)))
+            .get_result(conn)
+    }
+
+    pub fn remove(conn: &PooledPgConnection, GuildId(guild): GuildId, currency: &str, label: &str) -> QueryResult<usize> {
+        diesel::delete(unit_definition.filter(guild_id.eq(guild as i64)).filter(currency_col.eq(currency)).filter(label_col.eq(label)))
+            .execute(conn)
     }
 }
-pub use auction_manager::{AuctionManager, GetAuctionError, TendError};
 
 
+// 利用者が入力・閲覧する日時のタイムゾーン。保存と比較はUTCで行う
+pub mod clock {
+    use chrono::{DateTime, NaiveDateTime, TimeZone, Utc};
+    use chrono_tz::{Tz, Asia::Tokyo};
+    use std::env;
+
+    // 環境変数TIME_ZONE(ex. Asia/Tokyo)で変更できる。未設定・不正な値ならAsia/Tokyo
+    pub fn zone() -> Tz {
+        env::var("TIME_ZONE").ok().and_then(|name| name.parse().ok()).unwrap_or(Tokyo)
+    }
+
+    // 現在のタイムゾーンでの日時
+    pub fn local_now() -> NaiveDateTime {
+        to_local(&Utc::now())
+    }
+
+    pub fn to_local(time: &DateTime<Utc>) -> NaiveDateTime {
+        time.with_timezone(&zone()).naive_local()
+    }
+
+    // 入力された日時をUTCにする。夏時間の切り替えで存在しない時刻ならNone
+    pub fn from_local(time: &NaiveDateTime) -> Option<DateTime<Utc>> {
+        zone().from_local_datetime(time).earliest().map(|time| time.with_timezone(&Utc))
+    }
+}
+
 pub mod formats {
-    use chrono::Duration;
+    use chrono::{DateTime, Duration, NaiveDate, NaiveDateTime, Datelike, Utc};
     use regex::Regex;
-    use std::collections::HashMap;
     use serenity::prelude::*;
     use serenity::model::{guild::{Member, Guild}, user::User};
 
-    const DATETIME_PATTERN: &str = r"^(?P<year>\d{4})[-/](?P<month>\d{1,2})[-/](?P<day>\d{1,2})[-\stT](?P<hour>\d{1,2}):(?P<minute>\d{1,2})$";
+    // 数字は半角の0-9だけを受け付ける。\dは他の文字体系の数字にも一致し、parseで失敗する
+    const DATETIME_PATTERN: &str = r"^(?P<year>[0-9]{4})[-/](?P<month>[0-9]{1,2})[-/](?P<day>[0-9]{1,2})[-\stT](?P<hour>[0-9]{1,2}):(?P<minute>[0-9]{1,2})$";
     const DURATION_PATTERN: &str = 
         r"^(?ix)
-        ((?P<month>\d{1,4})(?P<m_unit>M))?
-        ((?P<week>\d{1,5})w)?
-        ((?P<day>\d{1,5})d)?
-        ((?P<hour>\d{1,5})h)?
-        ((?P<minute>\d{1,5})m)?
+        ((?P<month>[0-9]{1,4})(?P<m_unit>M))?
+        ((?P<week>[0-9]{1,5})w)?
+        ((?P<day>[0-9]{1,5})d)?
+        ((?P<hour>[0-9]{1,5})h)?
+        ((?P<minute>[0-9]{1,5})m)?
+        $";
+    const STACK_PATTERN: &str = r"^(?P<integer>[0-9]{1,18})(\.(?P<fraction>[0-9]{1,6}))?(?P<unit>[^0-9]*)$";
+    const END_EXTENSION_PATTERN: &str = r"^(?P<window>[0-9]{1,4})\s+(?P<minutes>[0-9]{1,4})$";
+    // 明日20時, 5/14 20:00, 5月14日午後8時半, 来週土曜21時, 21時 など
+    const NATURAL_DATETIME_PATTERN: &str =
+        r"^(?x)
+        (
+            ((?P<year>[0-9]{4})[-/年])?(?P<month>[0-9]{1,2})[-/月](?P<day>[0-9]{1,2})日?
+            | (?P<relative_day>今日|きょう|明日|あした|あす|明後日|あさって)
+            | (?P<week>来週|再来週)?の?(?P<weekday>[月火水木金土日])曜日?
+        )?
+        \s*の?\s*
+        (?P<ampm>午前|午後)?
+        (?P<hour>[0-9]{1,2})
+        (時((?P<minute>[0-9]{1,2})分|(?P<half>半))? | :(?P<colon_minute>[0-9]{2}))
+        $";
+    // 3日後, 3日4時間, 1ヶ月2週間 など
+    const NATURAL_DURATION_PATTERN: &str =
+        r"^(?x)
+        ((?P<month>[0-9]{1,4})[ヶケカかヵ箇]月)?
+        ((?P<week>[0-9]{1,5})週間?)?
+        ((?P<day>[0-9]{1,5})日)?
+        ((?P<hour>[0-9]{1,5})時間)?
+        ((?P<minute>[0-9]{1,5})分)?
+        (?P<after>後)?
         $";
-    const STACK_PATTERN: &str = r"^(?P<value>\d{1,8})(?P<unit>(st|lc)?)$";
 
     pub fn datetime(text: &str) -> Option<(i32, u32, u32, u32, u32)> {
         let pattern = Regex::new(DATETIME_PATTERN).unwrap();
@@ -237,53 +1626,295 @@ pub mod formats {
         Some((month, duration))
     }
 
-    pub fn stack_to_int(text: &str) -> Option<i32> {
-        let units = HashMap::from([
-            ("", 1),
-            ("st", 64),
-            ("lc", 3456),
-        ]);
-        let pattern = Regex::new(STACK_PATTERN).unwrap();
-        let mut res = 0;
-
-        for term in text.to_lowercase().split("+").map(str::trim) {
-            if let Some(cap) = pattern.captures(term) {
-                let value: i32 = cap.name("value").unwrap().as_str().parse().unwrap();
-                let unit = units.get(cap.name("unit").unwrap().as_str()).unwrap();
-                res += unit * value;
-            } else {
-                return None
+    // 日本語の日時・期間の入力をnowを基準に日時にする。datetime, durationで読めない入力に使う
+    // 年を省略した日付は次に来るその日、曜日は次に来るその曜日(来週・再来週なら翌週・翌々週の曜日)にする
+    // 「3日4時間」のような期間は「後」を省略できる
+    pub fn natural_datetime(text: &str, now: NaiveDateTime) -> Option<NaiveDateTime> {
+        // 全角の数字・記号は半角として読む
+        let text = text.trim().chars().map(|c| match c {
+            '０'..='９' => std::char::from_u32(c as u32 - '０' as u32 + '0' as u32).unwrap(),
+            '：' => ':',
+            '／' => '/',
+            '　' => ' ',
+            _ => c,
+        }).collect::<String>();
+
+        if let Some(cap) = Regex::new(NATURAL_DURATION_PATTERN).unwrap().captures(&text) {
+            let value = |name: &str| cap.name(name).map(|s| s.as_str().parse::<i64>().unwrap());
+            let (month, week, day, hour, minute) = (value("month"), value("week"), value("day"), value("hour"), value("minute"));
+            if [month, week, day, hour, minute].iter().all(Option::is_none) {
+                return None;
+            }
+            // 「3日」だけでは日付と区別できないので「後」を必須にする
+            if cap.name("after").is_none() && [month, week, hour, minute].iter().all(Option::is_none) {
+                return None;
             }
+            let time = add_months(now, month.unwrap_or(0) as i32);
+            return Some(time
+                + Duration::weeks(week.unwrap_or(0))
+                + Duration::days(day.unwrap_or(0))
+                + Duration::hours(hour.unwrap_or(0))
+                + Duration::minutes(minute.unwrap_or(0)));
+        }
+
+        let cap = Regex::new(NATURAL_DATETIME_PATTERN).unwrap().captures(&text)?;
+        let mut hour: u32 = cap.name("hour").unwrap().as_str().parse().unwrap();
+        let minute: u32 = match (cap.name("minute").or_else(|| cap.name("colon_minute")), cap.name("half")) {
+            (Some(minute), _) => minute.as_str().parse().unwrap(),
+            (None, Some(_)) => 30,
+            (None, None) => 0,
+        };
+        match cap.name("ampm").map(|s| s.as_str()) {
+            Some(_) if hour > 12 => return None,
+            Some("午前") if hour == 12 => hour = 0,
+            Some("午後") if hour < 12 => hour += 12,
+            _ => {},
+        }
+        // 24:00は翌日の0:00として扱う
+        if minute >= 60 || hour > 24 || hour == 24 && minute != 0 {
+            return None;
+        }
+        let at = |date: NaiveDate| date.and_hms(0, 0, 0) + Duration::hours(hour as i64) + Duration::minutes(minute as i64);
+
+        let today = now.date();
+        if let Some(month) = cap.name("month") {
+            let month: u32 = month.as_str().parse().unwrap();
+            let day: u32 = cap.name("day").unwrap().as_str().parse().unwrap();
+            if let Some(year) = cap.name("year") {
+                return NaiveDate::from_ymd_opt(year.as_str().parse().unwrap(), month, day).map(at);
+            }
+            let this_year = NaiveDate::from_ymd_opt(today.year(), month, day).map(at);
+            return match this_year {
+                Some(time) if time > now => Some(time),
+                _ => NaiveDate::from_ymd_opt(today.year() + 1, month, day).map(at),
+            };
+        }
+        if let Some(relative_day) = cap.name("relative_day") {
+            let days = match relative_day.as_str() {
+                "今日" | "きょう" => 0,
+                "明日" | "あした" | "あす" => 1,
+                _ => 2,
+            };
+            return Some(at(today + Duration::days(days)));
+        }
+
+        // 日付を省略した時刻は次に来るその時刻にする
+        let weekday = match cap.name("weekday") {
+            Some(weekday) => "月火水木金土日".chars().position(|c| weekday.as_str().starts_with(c)).unwrap() as i64,
+            None => {
+                let time = at(today);
+                return Some(if time > now { time } else { time + Duration::days(1) });
+            }
+        };
+        let this_weekday = today.weekday().num_days_from_monday() as i64;
+        match cap.name("week").map(|s| s.as_str()) {
+            Some(week) => {
+                let weeks = if week == "来週" { 1 } else { 2 };
+                Some(at(today + Duration::days(weekday - this_weekday + 7 * weeks)))
+            },
+            None => {
+                let time = at(today + Duration::days((weekday - this_weekday + 7) % 7));
+                Some(if time > now { time } else { time + Duration::weeks(1) })
+            },
         }
-        Some(res)
     }
 
-    pub fn int_to_stack(mut value: i32) -> String {
-        let lc = value / 3456;
-        value %= 3456;
-        let st = value / 64;
-        value %= 64;
-        
-        let mut res = vec![];
-        if lc > 0 {
-            res.push(format!("{}LC", lc))
+    // 月数を加える。加えた月にない日付(ex. 1/31の1ヶ月後)は月末にする
+    pub fn add_months(time: NaiveDateTime, months: i32) -> NaiveDateTime {
+        let month = time.year() * 12 + time.month0() as i32 + months;
+        let (year, month) = (month.div_euclid(12), month.rem_euclid(12) as u32 + 1);
+        NaiveDate::from_ymd(year, month, time.day().min(last_day(year, month))).and_time(time.time())
+    }
+
+    // 「延長判定の分数 延長する分数」の形式
+    pub fn end_extension(text: &str) -> Option<(i32, i32)> {
+        let pattern = Regex::new(END_EXTENSION_PATTERN).unwrap();
+        let cap = match pattern.captures(text.trim()) {
+            Some(cap) => cap,
+            None => return None,
+        };
+        let window = cap.name("window").unwrap().as_str().parse().unwrap();
+        let minutes = cap.name("minutes").unwrap().as_str().parse().unwrap();
+        if window == 0 || minutes == 0 {
+            return None;
         }
-        if st > 0 {
-            res.push(format!("{}st", st))
+        Some((window, minutes))
+    }
+
+    #[derive(Debug, Clone, PartialEq, Eq)]
+    pub enum PriceError {
+        Empty,
+        InvalidTerm { position: usize, term: String }, // position: 何番目の項か(1始まり)
+        NotWhole { position: usize, term: String }, // 小数が1個単位で割り切れない
+        UnknownUnit { position: usize, unit: String },
+        NonAsciiDigit { position: usize, term: String }, // 全角などの半角の0-9以外の数字
+        TooLarge,
+    }
+
+    impl std::fmt::Display for PriceError {
+        fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+            match self {
+                Self::Empty => write!(f, "価格が入力されていません"),
+                Self::InvalidTerm { position, term } => write!(f, "{}番目の「{}」が価格の形式になっていません", position, term),
+                Self::NotWhole { position, term } => write!(f, "{}番目の「{}」が1個単位で割り切れません", position, term),
+                Self::UnknownUnit { position, unit } => write!(f, "{}番目の単位「{}」は定義されていません", position, unit),
+                Self::NonAsciiDigit { position, term } => write!(f, "{}番目の「{}」: 数字は半角の0-9で入力してください", position, term),
+                Self::TooLarge => write!(f, "値が大きすぎます (扱える上限は{}個です)", i64::MAX),
+            }
         }
-        if value > 0 {
-            res.push(format!("{}個", value))
+    }
+
+    // 価格の単位。aliasesは入力で使える別名(表示名は大文字小文字を区別せずに使える)
+    #[derive(Debug, Clone, PartialEq, Eq)]
+    pub struct Unit {
+        pub label: String,
+        pub aliases: Vec<String>,
+        pub multiplier: i32,
+    }
+
+    // 価格の読み取りと表示に使う単位の組。倍率の大きい順に並べる。1個(`個`または単位なし)は常に使える
+    #[derive(Debug, Clone)]
+    pub struct UnitSystem {
+        units: Vec<Unit>,
+    }
+
+    impl Default for UnitSystem {
+        fn default() -> Self {
+            Self {
+                units: vec![
+                    Unit { label: "LC".into(), aliases: vec!["ラージチェスト".into()], multiplier: 3456 },
+                    Unit { label: "st".into(), aliases: vec!["スタック".into()], multiplier: 64 },
+                ],
+            }
+        }
+    }
+
+    impl UnitSystem {
+        // 同じ表示名の単位は置き換え、ない単位は追加する
+        pub fn overlay<I: IntoIterator<Item = Unit>>(&mut self, units: I) {
+            for unit in units {
+                self.units.retain(|current| current.label.to_lowercase() != unit.label.to_lowercase());
+                self.units.push(unit);
+            }
+            self.units.sort_by(|a, b| b.multiplier.cmp(&a.multiplier));
+        }
+
+        pub fn units(&self) -> &[Unit] {
+            &self.units
+        }
+
+        fn multiplier(&self, name: &str) -> Option<i64> {
+            if name.is_empty() || name == "個" {
+                return Some(1);
+            }
+            self.units.iter()
+                .find(|unit| unit.label.to_lowercase() == name || unit.aliases.iter().any(|alias| alias.to_lowercase() == name))
+                .map(|unit| unit.multiplier as i64)
         }
 
-        res.join("+")
+        // 【〇LC+△ST+□】の形の価格を個数にする。各項は`1.5lc`や`0.5st`のように小数でもよく、
+        // 整数で計算して1個単位で割り切れない場合はエラーにする
+        pub fn parse(&self, text: &str) -> Result<i64, PriceError> {
+            let pattern = Regex::new(STACK_PATTERN).unwrap();
+            let text = text.trim().to_lowercase();
+            if text.is_empty() {
+                return Err(PriceError::Empty);
+            }
+
+            let mut res: i64 = 0;
+            for (i, term) in text.split("+").map(str::trim).enumerate() {
+                let invalid = || PriceError::InvalidTerm { position: i + 1, term: term.to_string() };
+                if term.chars().any(|c| c.is_numeric() && !c.is_ascii_digit()) {
+                    return Err(PriceError::NonAsciiDigit { position: i + 1, term: term.to_string() });
+                }
+                let cap = pattern.captures(term).ok_or_else(|| invalid())?;
+                let unit_name = cap.name("unit").unwrap().as_str().trim();
+                let unit = self.multiplier(unit_name)
+                    .ok_or_else(|| PriceError::UnknownUnit { position: i + 1, unit: unit_name.to_string() })?;
+                let integer: i64 = cap.name("integer").unwrap().as_str().parse().map_err(|_| invalid())?;
+                let mut value = integer.checked_mul(unit).ok_or(PriceError::TooLarge)?;
+                if let Some(fraction) = cap.name("fraction") {
+                    // 0.5st → 5 * 64 / 10
+                    let numerator: i64 = fraction.as_str().parse().map_err(|_| invalid())?;
+                    let denominator = 10i64.pow(fraction.as_str().len() as u32);
+                    let scaled = numerator.checked_mul(unit).ok_or(PriceError::TooLarge)?;
+                    if scaled % denominator != 0 {
+                        return Err(PriceError::NotWhole { position: i + 1, term: term.to_string() });
+                    }
+                    value = value.checked_add(scaled / denominator).ok_or(PriceError::TooLarge)?;
+                }
+                res = res.checked_add(value).ok_or(PriceError::TooLarge)?;
+            }
+            Ok(res)
+        }
+
+        // 倍率の大きい単位から順に割り当てる
+        pub fn to_stack(&self, mut value: i64) -> String {
+            let mut res = vec![];
+            for unit in self.units.iter().filter(|unit| unit.multiplier > 1) {
+                let count = value / unit.multiplier as i64;
+                value %= unit.multiplier as i64;
+                if count > 0 {
+                    res.push(format!("{}{}", count, unit.label))
+                }
+            }
+            if value > 0 {
+                res.push(format!("{}個", value))
+            }
+
+            res.join("+")
+        }
+
+        // 単位を使う価格には個数も併記する
+        pub fn with_raw(&self, value: i64) -> String {
+            let mut res = self.to_stack(value);
+            if self.units.iter().any(|unit| unit.multiplier > 1 && value >= unit.multiplier as i64) {
+                res.push_str(&format!(" ({})", value));
+            }
+            res
+        }
+
+        // 単位の一覧 ex. LC = 3456個 (別名: ラージチェスト)
+        pub fn describe(&self) -> String {
+            self.units.iter().map(|unit| {
+                if unit.aliases.is_empty() {
+                    format!("{} = {}個", unit.label, unit.multiplier)
+                } else {
+                    format!("{} = {}個 (別名: {})", unit.label, unit.multiplier, unit.aliases.join(", "))
+                }
+            }).collect::<Vec<_>>().join("\n")
+        }
+    }
+
+    // 設定されたタイムゾーンで表示する
+    pub fn datetime_text(time: &DateTime<Utc>) -> String {
+        super::clock::to_local(time).format("%Y/%m/%d %H:%M").to_string()
     }
 
-    pub fn stack_with_raw(value: i32) -> String {
-        let mut res = int_to_stack(value);
-        if value >= 64 {
-            res.push_str(&format!(" ({})", value));
+    // 残り時間を「1日2時間3分」の形式にする。1分未満は「まもなく終了」
+    // 残り時間の表示。表示が変わった時だけembedを編集するので、残りが長いほど粗く表示する
+    // 1日以上は時間単位、1時間以上は10分単位(切り捨て)、1時間未満は分単位
+    pub fn remaining_text(remaining: Duration) -> String {
+        if remaining < Duration::minutes(1) {
+            return "まもなく終了".into();
+        }
+        let (days, hours, mut minutes) = (remaining.num_days(), remaining.num_hours() % 24, remaining.num_minutes() % 60);
+        if days > 0 {
+            minutes = 0;
+        } else if hours > 0 {
+            minutes -= minutes % 10;
+        }
+        let mut text = String::new();
+        if days > 0 {
+            text.push_str(&format!("{}日", days));
+        }
+        if hours > 0 {
+            text.push_str(&format!("{}時間", hours));
         }
-        res
+        if minutes > 0 {
+            text.push_str(&format!("{}分", minutes));
+        }
+        text
     }
 
     pub fn last_day(year: i32, month: u32) -> u32 {
@@ -318,11 +1949,14 @@ pub mod formats {
 }
 
 
+pub use formats::UnitSystem;
+
+
 pub mod discord_helper {
     use std::time::Duration;
     use serenity::prelude::*;
     use serenity::Result as SrnResult;
-    use serenity::model::{channel::Message, id::{MessageId, ChannelId}};
+    use serenity::model::{channel::{Channel, Message}, guild::Guild, id::{MessageId, ChannelId, RoleId}, permissions::Permissions};
 
     
     pub async fn await_right_reply<F, T>(ctx: &Context, msg: &Message, filter: F) -> Option<T> where
@@ -351,6 +1985,34 @@ pub mod discord_helper {
         None
     }
 
+    pub async fn guild_of(ctx: &Context, channel_id: ChannelId) -> Option<Guild> {
+        if let Some(Channel::Guild(channel)) = channel_id.to_channel_cached(&ctx.cache).await {
+            channel.guild_id.to_guild_cached(&ctx.cache).await
+        } else {
+            None
+        }
+    }
+
+    // サーバーの所有者か、メッセージの管理(または管理者)権限を持つロールがあるか
+    // チャンネルごとの権限の上書きは考慮しない
+    pub async fn is_moderator(ctx: &Context, msg: &Message) -> bool {
+        let guild = match msg.guild(ctx).await {
+            Some(guild) => guild,
+            None => return false,
+        };
+        if guild.owner_id == msg.author.id {
+            return true;
+        }
+        let member = match guild.member(ctx, msg.author.id).await {
+            Ok(member) => member,
+            Err(_) => return false,
+        };
+        let everyone = RoleId(guild.id.0);
+        member.roles.iter().chain(std::iter::once(&everyone))
+            .filter_map(|role_id| guild.roles.get(role_id))
+            .any(|role| role.permissions.intersects(Permissions::MANAGE_MESSAGES | Permissions::ADMINISTRATOR))
+    }
+
     pub async fn purge(ctx: &Context, channel_id: ChannelId, after: MessageId) -> SrnResult<()> {
         let messages = channel_id.messages(ctx, |g| {
             g.after(after)
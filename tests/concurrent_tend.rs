@@ -0,0 +1,80 @@
+// 1つのオークションへの同時入札で、受け付けた入札が失われず順に記録されることを確かめる
+// auction_info・channel_auction等を作成済みのテスト用データベースが必要
+// TEST_DATABASE_URLを設定して `cargo test -- --ignored` で実行する
+use std::env;
+use std::sync::{Arc, Barrier};
+use std::thread;
+use chrono::{Duration, Utc};
+use diesel::prelude::*;
+use diesel::pg::PgConnection;
+use diesel::r2d2::{ConnectionManager, Pool};
+use kgx_rust::models::{AuctionInfo, AuctionType, NewAuctionInfo, TendHistory};
+use kgx_rust::schema::auction_info::dsl::{auction_info, id as auction_id_col};
+use kgx_rust::schema::tend_history::dsl::{tend_history, auction_id as history_auction_col, id as history_id_col, removed_at};
+use kgx_rust::utils::AuctionManager;
+
+const BIDDERS: u32 = 16;
+const TENDS_PER_BIDDER: i64 = 5;
+
+#[test]
+#[ignore]
+fn concurrent_tends_keep_every_accepted_tend_in_order() {
+    let url = env::var("TEST_DATABASE_URL").expect("TEST_DATABASE_URL is not set");
+    let pool = Pool::builder().max_size(BIDDERS + 1).build(ConnectionManager::<PgConnection>::new(url)).unwrap();
+    let conn = pool.get().unwrap();
+
+    // 実在のチャンネルと重ならないよう負のIDを使う
+    let channel_id = -Utc::now().timestamp_millis();
+    let info: AuctionInfo = diesel::insert_into(auction_info).values(&NewAuctionInfo {
+        channel_id,
+        owner_id: 1,
+        item: "同時入札のテスト".into(),
+        end_time: Utc::now() + Duration::days(1),
+        start_price: 1,
+        bin_price: None,
+        unit: "".into(),
+        notice: "".into(),
+        min_increment: None,
+        min_increment_percent: None,
+        extension_window: None,
+        extension_minutes: None,
+        reserve_price: None,
+        auction_type: AuctionType::English.as_str().into(),
+        second_price: false,
+        quantity: 1,
+    }).get_result(&conn).unwrap();
+
+    // 全員が揃ってから、入札者ごとに少しずつ高い価格で入札を繰り返す
+    let barrier = Arc::new(Barrier::new(BIDDERS as usize));
+    let handles = (0..BIDDERS).map(|bidder| {
+        let pool = pool.clone();
+        let barrier = barrier.clone();
+        thread::spawn(move || {
+            let conn = pool.get().unwrap();
+            let mut manager = AuctionManager::from_id(&conn, info.id).unwrap().unwrap();
+            let tender_id = 1000 + bidder as u64;
+            barrier.wait();
+            let mut accepted = Vec::new();
+            for round in 0..TENDS_PER_BIDDER {
+                let price = 100 + round * BIDDERS as i64 + bidder as i64;
+                if let Ok(outcome) = manager.tend(&conn, tender_id, "入札者", tender_id, price, 1).unwrap() {
+                    accepted.extend(outcome.tends.into_iter().map(|tend| tend.id));
+                }
+            }
+            accepted
+        })
+    }).collect::<Vec<_>>();
+    let mut accepted = handles.into_iter().flat_map(|handle| handle.join().unwrap()).collect::<Vec<_>>();
+    accepted.sort();
+
+    let history = tend_history.filter(history_auction_col.eq(info.id)).filter(removed_at.is_null())
+        .order(history_id_col.asc()).load::<TendHistory>(&conn).unwrap();
+    diesel::delete(auction_info.filter(auction_id_col.eq(info.id))).execute(&conn).unwrap();
+
+    assert!(!accepted.is_empty());
+    assert_eq!(history.iter().map(|tend| tend.id).collect::<Vec<_>>(), accepted, "受け付けた入札と記録が一致しない");
+    for pair in history.windows(2) {
+        assert!(pair[0].price < pair[1].price, "入札額が増加していない: {} -> {}", pair[0].price, pair[1].price);
+        assert_ne!(pair[0].tender_id, pair[1].tender_id, "同じ入札者が連続している");
+    }
+}
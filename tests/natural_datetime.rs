@@ -0,0 +1,145 @@
+// 日本語の日時・期間の入力の読み取りを確かめる
+// 基準の日時は2026/10/18(日) 12:00
+use chrono::{NaiveDate, NaiveDateTime};
+use kgx_rust::utils::formats::{datetime, duration, end_extension, natural_datetime, PriceError, UnitSystem};
+
+fn now() -> NaiveDateTime {
+    NaiveDate::from_ymd(2026, 10, 18).and_hms(12, 0, 0)
+}
+
+fn at(year: i32, month: u32, day: u32, hour: u32, minute: u32) -> NaiveDateTime {
+    NaiveDate::from_ymd(year, month, day).and_hms(hour, minute, 0)
+}
+
+#[test]
+fn reads_relative_days() {
+    let cases = [
+        ("今日20時", at(2026, 10, 18, 20, 0)),
+        ("明日20時", at(2026, 10, 19, 20, 0)),
+        ("あした9時", at(2026, 10, 19, 9, 0)),
+        ("明後日の21時", at(2026, 10, 20, 21, 0)),
+        ("明日24時", at(2026, 10, 20, 0, 0)),
+    ];
+    for (text, expected) in cases.iter() {
+        assert_eq!(natural_datetime(text, now()), Some(*expected), "{}", text);
+    }
+}
+
+#[test]
+fn reads_weekdays() {
+    let cases = [
+        ("土曜21時", at(2026, 10, 24, 21, 0)),
+        ("土曜日21時", at(2026, 10, 24, 21, 0)),
+        // 今日の曜日は、時刻を過ぎていれば翌週にする
+        ("日曜13時", at(2026, 10, 18, 13, 0)),
+        ("日曜12時", at(2026, 10, 25, 12, 0)),
+        // 来週・再来週は月曜始まりで数える
+        ("来週の金曜21時", at(2026, 10, 23, 21, 0)),
+        ("来週金曜日の21時", at(2026, 10, 23, 21, 0)),
+        ("再来週月曜10時", at(2026, 10, 26, 10, 0)),
+    ];
+    for (text, expected) in cases.iter() {
+        assert_eq!(natural_datetime(text, now()), Some(*expected), "{}", text);
+    }
+}
+
+#[test]
+fn reads_durations() {
+    let cases = [
+        ("3日後", at(2026, 10, 21, 12, 0)),
+        ("30分後", at(2026, 10, 18, 12, 30)),
+        ("2時間", at(2026, 10, 18, 14, 0)),
+        ("3日4時間", at(2026, 10, 21, 16, 0)),
+        ("1週間後", at(2026, 10, 25, 12, 0)),
+        ("1ヶ月2週間", at(2026, 12, 2, 12, 0)),
+    ];
+    for (text, expected) in cases.iter() {
+        assert_eq!(natural_datetime(text, now()), Some(*expected), "{}", text);
+    }
+}
+
+#[test]
+fn reads_dates() {
+    let cases = [
+        // 過ぎた日付は翌年にする
+        ("5/14 20:00", at(2027, 5, 14, 20, 0)),
+        ("12/24 20時", at(2026, 12, 24, 20, 0)),
+        ("2027年5月14日20時", at(2027, 5, 14, 20, 0)),
+        ("2027/5/14 20:00", at(2027, 5, 14, 20, 0)),
+        ("5月14日午後8時半", at(2027, 5, 14, 20, 30)),
+    ];
+    for (text, expected) in cases.iter() {
+        assert_eq!(natural_datetime(text, now()), Some(*expected), "{}", text);
+    }
+}
+
+#[test]
+fn reads_times() {
+    let cases = [
+        ("20時", at(2026, 10, 18, 20, 0)),
+        ("20時半", at(2026, 10, 18, 20, 30)),
+        ("20時15分", at(2026, 10, 18, 20, 15)),
+        ("午後8時", at(2026, 10, 18, 20, 0)),
+        ("午後8時半", at(2026, 10, 18, 20, 30)),
+        ("午後12時", at(2026, 10, 19, 12, 0)),
+        ("午前12時", at(2026, 10, 19, 0, 0)),
+        ("午前0時", at(2026, 10, 19, 0, 0)),
+        ("24時", at(2026, 10, 19, 0, 0)),
+        // 過ぎた時刻は翌日にする
+        ("9時", at(2026, 10, 19, 9, 0)),
+    ];
+    for (text, expected) in cases.iter() {
+        assert_eq!(natural_datetime(text, now()), Some(*expected), "{}", text);
+    }
+}
+
+#[test]
+fn reads_full_width_digits() {
+    let cases = [
+        ("明日２０時", at(2026, 10, 19, 20, 0)),
+        ("３日後", at(2026, 10, 21, 12, 0)),
+        ("５／１４　２０：００", at(2027, 5, 14, 20, 0)),
+        ("　午後８時半　", at(2026, 10, 18, 20, 30)),
+    ];
+    for (text, expected) in cases.iter() {
+        assert_eq!(natural_datetime(text, now()), Some(*expected), "{}", text);
+    }
+}
+
+#[test]
+fn rejects_invalid_input() {
+    let cases = [
+        "",
+        "abc",
+        // 日付と区別できない期間
+        "3日",
+        // 時刻の省略
+        "明日",
+        "来週の金曜",
+        // 範囲外の時刻・日付
+        "25時",
+        "24時30分",
+        "20時60分",
+        "午後13時",
+        "2/30 20時",
+        // 半角の0-9以外の数字
+        "٣日後",
+        "明日٢٠時",
+        "५/१४ 20:00",
+    ];
+    for text in cases.iter() {
+        assert_eq!(natural_datetime(text, now()), None, "{}", text);
+    }
+}
+
+#[test]
+fn other_formats_reject_non_ascii_digits() {
+    assert_eq!(datetime("٢٠٢٦/05/14 20:00"), None);
+    assert_eq!(duration("٣d"), None);
+    assert_eq!(end_extension("٥ ١٠"), None);
+
+    let units = UnitSystem::default();
+    assert_eq!(units.parse("10"), Ok(10));
+    assert_eq!(units.parse("１０"), Err(PriceError::NonAsciiDigit { position: 1, term: "１０".into() }));
+    assert_eq!(units.parse("10+٥"), Err(PriceError::NonAsciiDigit { position: 2, term: "٥".into() }));
+}
@@ -0,0 +1,73 @@
+// 自動入札と手動の入札が同額の場合に、先に登録した自動入札が勝つことを確かめる
+// auction_info・channel_auction等を作成済みのテスト用データベースが必要
+// TEST_DATABASE_URLを設定して `cargo test -- --ignored` で実行する
+use std::env;
+use chrono::{Duration, Utc};
+use diesel::prelude::*;
+use diesel::pg::PgConnection;
+use diesel::r2d2::{ConnectionManager, Pool, PooledConnection};
+use kgx_rust::models::{AuctionInfo, AuctionType, NewAuctionInfo};
+use kgx_rust::schema::auction_info::dsl::{auction_info, id as auction_id_col};
+use kgx_rust::utils::AuctionManager;
+
+const PROXY_TENDER: u64 = 1001;
+const MANUAL_TENDER: u64 = 1002;
+
+fn connect() -> PooledConnection<ConnectionManager<PgConnection>> {
+    let url = env::var("TEST_DATABASE_URL").expect("TEST_DATABASE_URL is not set");
+    Pool::builder().max_size(1).build(ConnectionManager::<PgConnection>::new(url)).unwrap().get().unwrap()
+}
+
+// 実在のチャンネルと重ならないよう負のIDを使う
+fn create_auction(conn: &PgConnection) -> AuctionInfo {
+    diesel::insert_into(auction_info).values(&NewAuctionInfo {
+        channel_id: -Utc::now().timestamp_millis(),
+        owner_id: 1,
+        item: "自動入札のテスト".into(),
+        end_time: Utc::now() + Duration::days(1),
+        start_price: 1,
+        bin_price: None,
+        unit: "".into(),
+        notice: "".into(),
+        min_increment: None,
+        min_increment_percent: None,
+        extension_window: None,
+        extension_minutes: None,
+        reserve_price: None,
+        auction_type: AuctionType::English.as_str().into(),
+        second_price: false,
+        quantity: 1,
+    }).get_result(conn).unwrap()
+}
+
+// 自動入札の上限額1000に対して手動でmanual_priceを入札し、(最高額入札者, 最高入札額)を返す
+fn leader_after_manual_tend(manual_price: i64) -> (u64, i64) {
+    let conn = connect();
+    let info = create_auction(&conn);
+    let mut manager = AuctionManager::from_id(&conn, info.id).unwrap().unwrap();
+    manager.set_proxy(&conn, PROXY_TENDER, "自動入札者", 1000).unwrap().unwrap();
+    manager.tend(&conn, MANUAL_TENDER, "手動入札者", MANUAL_TENDER, manual_price, 1).unwrap().unwrap();
+
+    let manager = AuctionManager::from_id(&conn, info.id).unwrap().unwrap();
+    diesel::delete(auction_info.filter(auction_id_col.eq(info.id))).execute(&conn).unwrap();
+    let last_tend = manager.tend.last().unwrap();
+    (last_tend.tender_id, last_tend.price)
+}
+
+#[test]
+#[ignore]
+fn earlier_proxy_wins_a_tie_with_a_manual_tend() {
+    assert_eq!(leader_after_manual_tend(1000), (PROXY_TENDER, 1000));
+}
+
+#[test]
+#[ignore]
+fn proxy_responds_below_the_min_increment_up_to_its_max() {
+    assert_eq!(leader_after_manual_tend(999), (PROXY_TENDER, 1000));
+}
+
+#[test]
+#[ignore]
+fn manual_tend_above_the_proxy_max_wins() {
+    assert_eq!(leader_after_manual_tend(1001), (MANUAL_TENDER, 1001));
+}